@@ -0,0 +1,20 @@
+//! exst command line interface
+
+use exst_repl::context::Context;
+use exst_repl::exec::Executor;
+use std::env;
+use std::process;
+
+fn main() {
+    match Context::new(env::args()) {
+        Ok(context) => {
+            let executor = Executor::new(context);
+            process::exit(executor.exec());
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            eprint!("{}", Context::usage());
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! VM construction
+//!
+//! `VmBuilder` bundles the usual setup steps — create a machine,
+//! register every primitive word, run the preload scripts — and
+//! applies optional execution limits, so an embedder gets a ready to
+//! use machine from one call.
+
+use super::primitive;
+use super::resource::Resources;
+use super::vm::StackCheckMode;
+use super::vm::Vm;
+use super::vm::VmErrorReason;
+use std::fmt;
+
+/// collects settings and produces a fully initialized machine
+pub struct VmBuilder<R> {
+    resources: R,
+    stack_check_mode: Option<StackCheckMode>,
+    data_stack_limit: Option<usize>,
+    instruction_budget: Option<u64>,
+    max_script_depth: Option<usize>,
+    strict_compare: bool,
+    interning_enabled: bool,
+}
+
+impl<R: Resources + 'static> VmBuilder<R> {
+    /// a builder with the default settings and the given resources
+    pub fn new(resources: R) -> Self {
+        VmBuilder {
+            resources,
+            stack_check_mode: None,
+            data_stack_limit: None,
+            instruction_budget: None,
+            max_script_depth: None,
+            strict_compare: false,
+            interning_enabled: true,
+        }
+    }
+
+    /// cap the data stack depth
+    pub fn data_stack_limit(mut self, limit: usize) -> Self {
+        self.data_stack_limit = Some(limit);
+        self
+    }
+
+    /// cap the number of instructions the machine may execute
+    pub fn instruction_budget(mut self, budget: u64) -> Self {
+        self.instruction_budget = Some(budget);
+        self
+    }
+
+    /// cap the script call depth
+    pub fn max_script_depth(mut self, max: usize) -> Self {
+        self.max_script_depth = Some(max);
+        self
+    }
+
+    /// require comparison operands of the same variant
+    pub fn strict_compare(mut self, strict: bool) -> Self {
+        self.strict_compare = strict;
+        self
+    }
+
+    /// how declared stack effects are verified
+    pub fn stack_check_mode(mut self, mode: StackCheckMode) -> Self {
+        self.stack_check_mode = Some(mode);
+        self
+    }
+
+    /// turn string literal interning on or off
+    pub fn interning_enabled(mut self, enabled: bool) -> Self {
+        self.interning_enabled = enabled;
+        self
+    }
+
+    /// build the machine: register every primitive word, run the
+    /// preload scripts and apply the configured settings
+    ///
+    /// The limits only apply to the finished machine; the preload
+    /// phase runs without them. An error from the preload phase is
+    /// returned instead of a half-initialized machine.
+    pub fn build<T, E>(self) -> Result<Vm<T, E>, VmErrorReason<E>>
+    where
+        T: fmt::Debug + fmt::Display + Ord,
+        E: fmt::Debug,
+    {
+        let mut vm = Vm::new(self.resources);
+        primitive::initialize(&mut vm)?;
+        if let Some(mode) = self.stack_check_mode {
+            vm.set_stack_check_mode(mode);
+        }
+        vm.set_interning_enabled(self.interning_enabled);
+        vm.set_data_stack_limit(self.data_stack_limit);
+        vm.set_instruction_budget(self.instruction_budget);
+        vm.set_max_script_depth(self.max_script_depth);
+        vm.set_strict_compare(self.strict_compare);
+        Ok(vm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::resource::BufferResources;
+    use crate::lang::tokenizer::new_token_stream_from_string;
+    use std::rc::Rc;
+
+    fn run(vm: &mut Vm<i32, i32>, script: &str) -> Result<(), VmErrorReason<i32>> {
+        vm.call_script(Box::new(new_token_stream_from_string(
+            String::from(script),
+            String::from("test"),
+        )));
+        vm.exec()
+    }
+
+    #[test]
+    fn test_build_and_run() {
+        let resources = Rc::new(BufferResources::new());
+        let mut vm: Vm<i32, i32> = VmBuilder::new(Rc::clone(&resources)).build().unwrap();
+        run(&mut vm, "1 2 + .").unwrap();
+        assert_eq!(resources.stdout(), "3 ");
+    }
+
+    #[test]
+    fn test_instruction_budget() {
+        let mut vm: Vm<i32, i32> = VmBuilder::new(BufferResources::new())
+            .instruction_budget(1000)
+            .build()
+            .unwrap();
+        match run(&mut vm, ": w recursable 1 w ; w") {
+            Err(VmErrorReason::InstructionBudgetExceeded) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_data_stack_limit() {
+        let mut vm: Vm<i32, i32> = VmBuilder::new(BufferResources::new())
+            .data_stack_limit(8)
+            .build()
+            .unwrap();
+        match run(&mut vm, ": w recursable 1 w ; w") {
+            Err(VmErrorReason::DataStackLimitExceeded(8)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
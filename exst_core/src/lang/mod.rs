@@ -0,0 +1,18 @@
+//! language core modules
+
+pub mod tokenizer;
+pub mod resource;
+pub mod vm;
+pub mod primitive;
+pub mod builder;
+
+/// the machine integer type
+///
+/// Integer literals and `Value::IntValue` use this width. The default
+/// is `i32`; the `int64` cargo feature widens it to `i64`.
+#[cfg(not(feature = "int64"))]
+pub type VmInt = i32;
+
+/// the machine integer type (widened by the `int64` cargo feature)
+#[cfg(feature = "int64")]
+pub type VmInt = i64;
@@ -0,0 +1,282 @@
+//! arithmetic words
+//!
+//! Integer arithmetic wraps on overflow. When both operands are
+//! floats (or one is an integer and the other a float) the operation
+//! is carried out on floats.
+
+use super::util;
+use crate::lang::vm::value::Value;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use crate::lang::VmInt;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>)
+where
+    T: Ord,
+{
+    vm.define_pure_primitive_word("+", false, "a b -- c : c = a + b, wrapping", add);
+    vm.define_pure_primitive_word("-", false, "a b -- c : c = a - b, wrapping", sub);
+    vm.define_pure_primitive_word("*", false, "a b -- c : c = a * b, wrapping", mul);
+    vm.define_pure_primitive_word("+?", false, "a b -- c flag : checked a + b", checked_add);
+    vm.define_pure_primitive_word("-?", false, "a b -- c flag : checked a - b", checked_sub);
+    vm.define_pure_primitive_word("*?", false, "a b -- c flag : checked a * b", checked_mul);
+    vm.define_pure_primitive_word("/", false, "a b -- c : c = a / b", div);
+    vm.define_pure_primitive_word("mod", false, "a b -- c : c = a % b", modulo);
+    vm.define_pure_primitive_word("negate", false, "a -- b : b = -a", negate);
+    vm.define_pure_primitive_word("abs", false, "a -- b : b = |a|", abs);
+    vm.define_pure_primitive_word("min", false, "a b -- c : c = min(a, b)", min);
+    vm.define_pure_primitive_word("max", false, "a b -- c : c = max(a, b)", max);
+    vm.define_pure_primitive_word("1+", false, "a -- b : b = a + 1", inc);
+    vm.define_pure_primitive_word("1-", false, "a -- b : b = a - 1", dec);
+    vm.define_pure_primitive_word(">float", false, "n -- f : widen an int to a float", to_float);
+    vm.define_pure_primitive_word(">int", false, "f -- n : truncate a float toward zero", to_int);
+    vm.define_pure_primitive_word(
+        ">int-base",
+        false,
+        "s base -- n flag : parse a string as an int in the given radix",
+        to_int_base,
+    );
+    vm.define_pure_primitive_word("floor", false, "f -- f' : round down", floor);
+    vm.define_pure_primitive_word("ceil", false, "f -- f' : round up", ceil);
+    vm.define_pure_primitive_word("round", false, "f -- f' : round to the nearest", round);
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    "
+    : 2+ 1+ 1+ ;
+    : 2- 1- 1- ;
+    "
+}
+
+/// pop two numbers and push the result of the operation
+fn numeric_binop<T, E, F, G>(
+    vm: &mut Vm<T, E>,
+    int_op: F,
+    float_op: G,
+) -> Result<(), VmErrorReason<E>>
+where
+    F: Fn(VmInt, VmInt) -> Result<VmInt, VmErrorReason<E>>,
+    G: Fn(f64, f64) -> f64,
+{
+    let b = util::pop(vm)?;
+    let a = util::pop(vm)?;
+    let result = match (a.as_ref(), b.as_ref()) {
+        (Value::IntValue(a), Value::IntValue(b)) => Value::IntValue(int_op(*a, *b)?),
+        (Value::FloatValue(a), Value::FloatValue(b)) => Value::FloatValue(float_op(*a, *b)),
+        (Value::IntValue(a), Value::FloatValue(b)) => Value::FloatValue(float_op(*a as f64, *b)),
+        (Value::FloatValue(a), Value::IntValue(b)) => Value::FloatValue(float_op(*a, *b as f64)),
+        _ => return Err(VmErrorReason::TypeMismatchError("number")),
+    };
+    vm.data_stack_mut().push(Rc::new(result));
+    Ok(())
+}
+
+fn add<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    numeric_binop(vm, |a, b| Ok(a.wrapping_add(b)), |a, b| a + b)
+}
+
+fn sub<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    numeric_binop(vm, |a, b| Ok(a.wrapping_sub(b)), |a, b| a - b)
+}
+
+fn mul<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    numeric_binop(vm, |a, b| Ok(a.wrapping_mul(b)), |a, b| a * b)
+}
+
+fn div<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    numeric_binop(
+        vm,
+        |a, b| {
+            if b == 0 {
+                Err(VmErrorReason::ZeroDivisionError)
+            } else {
+                Ok(a.wrapping_div(b))
+            }
+        },
+        |a, b| a / b,
+    )
+}
+
+fn modulo<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    numeric_binop(
+        vm,
+        |a, b| {
+            if b == 0 {
+                Err(VmErrorReason::ZeroDivisionError)
+            } else {
+                Ok(a.wrapping_rem(b))
+            }
+        },
+        |a, b| a % b,
+    )
+}
+
+/// pop two ints and push the checked result with a success flag
+///
+/// On overflow the result is `0` and the flag is false; the value
+/// never silently wraps.
+fn checked_binop<T, E>(
+    vm: &mut Vm<T, E>,
+    op: fn(VmInt, VmInt) -> Option<VmInt>,
+) -> Result<(), VmErrorReason<E>> {
+    let b = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    match op(a, b) {
+        Some(c) => {
+            util::push_int(vm, c);
+            util::push_bool(vm, true);
+        }
+        None => {
+            util::push_int(vm, 0);
+            util::push_bool(vm, false);
+        }
+    }
+    Ok(())
+}
+
+fn checked_add<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    checked_binop(vm, VmInt::checked_add)
+}
+
+fn checked_sub<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    checked_binop(vm, VmInt::checked_sub)
+}
+
+fn checked_mul<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    checked_binop(vm, VmInt::checked_mul)
+}
+
+fn negate<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    match util::pop(vm)?.as_ref() {
+        Value::IntValue(a) => util::push_value(vm, Value::IntValue(a.wrapping_neg())),
+        Value::FloatValue(a) => util::push_value(vm, Value::FloatValue(-a)),
+        _ => return Err(VmErrorReason::TypeMismatchError("number")),
+    }
+    Ok(())
+}
+
+fn abs<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    match util::pop(vm)?.as_ref() {
+        Value::IntValue(a) => util::push_value(vm, Value::IntValue(a.wrapping_abs())),
+        Value::FloatValue(a) => util::push_value(vm, Value::FloatValue(a.abs())),
+        _ => return Err(VmErrorReason::TypeMismatchError("number")),
+    }
+    Ok(())
+}
+
+fn min<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    let b = util::pop(vm)?;
+    let a = util::pop(vm)?;
+    vm.data_stack_mut().push(if *a <= *b { a } else { b });
+    Ok(())
+}
+
+fn max<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    let b = util::pop(vm)?;
+    let a = util::pop(vm)?;
+    vm.data_stack_mut().push(if *a >= *b { a } else { b });
+    Ok(())
+}
+
+/// pop a float and push the result of the operation
+fn float_unop<T, E>(vm: &mut Vm<T, E>, op: fn(f64) -> f64) -> Result<(), VmErrorReason<E>> {
+    match util::pop(vm)?.as_ref() {
+        Value::FloatValue(f) => {
+            util::push_value(vm, Value::FloatValue(op(*f)));
+            Ok(())
+        }
+        _ => Err(VmErrorReason::TypeMismatchError("float")),
+    }
+}
+
+fn to_float<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    match util::pop(vm)?.as_ref() {
+        Value::IntValue(i) => {
+            util::push_value(vm, Value::FloatValue(*i as f64));
+            Ok(())
+        }
+        Value::FloatValue(f) => {
+            util::push_value(vm, Value::FloatValue(*f));
+            Ok(())
+        }
+        _ => Err(VmErrorReason::TypeMismatchError("number")),
+    }
+}
+
+fn to_int_base<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let base = util::pop_int(vm)?;
+    let base = u32::try_from(base)
+        .ok()
+        .filter(|b| (2..=36).contains(b))
+        .ok_or(VmErrorReason::TypeMismatchError("base in 2..=36"))?;
+    let body = util::pop_str(vm)?;
+    match VmInt::from_str_radix(&body, base) {
+        Ok(n) => {
+            util::push_int(vm, n);
+            util::push_bool(vm, true);
+        }
+        Err(_) => {
+            util::push_int(vm, 0);
+            util::push_bool(vm, false);
+        }
+    }
+    Ok(())
+}
+
+fn to_int<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    match util::pop(vm)?.as_ref() {
+        Value::FloatValue(f) => {
+            let truncated = f.trunc();
+            // the truncation must be representable; anything outside
+            // the machine integer range (or NaN) is an error, never a
+            // wrapped value. `VmInt::MAX as f64` rounds up to a value
+            // outside the range, so the upper bound is the exactly
+            // representable `-(VmInt::MIN as f64)`, excluded
+            if truncated >= VmInt::MIN as f64 && truncated < -(VmInt::MIN as f64) {
+                util::push_int(vm, truncated as VmInt);
+                Ok(())
+            } else {
+                Err(VmErrorReason::TypeMismatchError("float in int range"))
+            }
+        }
+        Value::IntValue(i) => {
+            util::push_int(vm, *i);
+            Ok(())
+        }
+        _ => Err(VmErrorReason::TypeMismatchError("number")),
+    }
+}
+
+fn floor<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    float_unop(vm, f64::floor)
+}
+
+fn ceil<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    float_unop(vm, f64::ceil)
+}
+
+fn round<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    float_unop(vm, f64::round)
+}
+
+fn inc<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let a = util::pop_int(vm)?;
+    util::push_int(vm, a.wrapping_add(1));
+    Ok(())
+}
+
+fn dec<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let a = util::pop_int(vm)?;
+    util::push_int(vm, a.wrapping_sub(1));
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! control flow words
+//!
+//! The control flow words are immediate; they compile jumps and patch
+//! their targets via the control flow stack. Words pick the branch
+//! polarity that fits: `if` and `until` jump on false (`BranchZero`),
+//! `loop` jumps on true (`Branch`).
+
+use super::util;
+use crate::lang::vm::buffer::Address;
+use crate::lang::vm::buffer::CodeAddress;
+use crate::lang::vm::value::Value;
+use crate::lang::vm::Instruction;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use std::rc::Rc;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>) {
+    vm.define_primitive_word("if", true, "flag -- : execute when true", if_word);
+    vm.define_primitive_word("else", true, "-- : alternative branch of if", else_word);
+    vm.define_primitive_word("endif", true, "-- : end of an if", endif_word);
+    vm.define_primitive_word("begin", true, "-- : start of a loop", begin_word);
+    vm.define_primitive_word("until", true, "flag -- : loop back while false", until_word);
+    vm.define_primitive_word("do", true, "limit index -- : start of a counted loop", do_word);
+    vm.define_primitive_word("loop", true, "-- : end of a counted loop", loop_word);
+    vm.define_primitive_word("i", false, "-- n : index of the innermost counted loop", index_word);
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    "
+    alias then endif
+    : times ( xt n -- ) local n local xt
+        n 0 > if n 0 do xt exec loop endif ;
+    : each-int ( xt lo hi -- ) local hi local lo local xt
+        hi lo > if hi lo do i xt exec loop endif ;
+    "
+}
+
+/// pop a code address from the control flow stack
+fn pop_controlflow<T, E>(vm: &mut Vm<T, E>) -> Result<CodeAddress, VmErrorReason<E>> {
+    vm.controlflow_stack_mut()
+        .pop()
+        .map_err(VmErrorReason::ControlflowStackAccessError)
+}
+
+/// retarget the jump instruction at the given address
+fn patch_jump_target<T, E>(
+    vm: &mut Vm<T, E>,
+    address: CodeAddress,
+    target: CodeAddress,
+) -> Result<(), VmErrorReason<E>> {
+    let instruction = vm
+        .code_buffer()
+        .get(address)
+        .map_err(VmErrorReason::CodeBufferAccessError)?;
+    let patched = match instruction {
+        Instruction::Branch(_) => Instruction::Branch(target),
+        Instruction::BranchZero(_) => Instruction::BranchZero(target),
+        Instruction::Jump(_) => Instruction::Jump(target),
+        _ => return Err(VmErrorReason::WordError("not a jump instruction")),
+    };
+    vm.code_buffer_mut()
+        .set(address, patched)
+        .map_err(VmErrorReason::CodeBufferAccessError)
+}
+
+fn if_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let branch = vm.code_buffer().here();
+    vm.compile_branch_zero(CodeAddress(Address::Root));
+    vm.controlflow_stack_mut().push(branch);
+    Ok(())
+}
+
+fn else_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let branch = pop_controlflow(vm)?;
+    let jump = vm.code_buffer().here();
+    vm.compile_instruction(Instruction::Jump(CodeAddress(Address::Root)));
+    let here = vm.code_buffer().here();
+    patch_jump_target(vm, branch, here)?;
+    vm.controlflow_stack_mut().push(jump);
+    Ok(())
+}
+
+fn endif_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let open = pop_controlflow(vm)?;
+    let here = vm.code_buffer().here();
+    patch_jump_target(vm, open, here)
+}
+
+fn begin_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let here = vm.code_buffer().here();
+    vm.controlflow_stack_mut().push(here);
+    Ok(())
+}
+
+fn until_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let begin = pop_controlflow(vm)?;
+    vm.compile_branch_zero(begin);
+    Ok(())
+}
+
+fn do_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    vm.compile_instruction(Instruction::CallPrimitive(rt_do));
+    let here = vm.code_buffer().here();
+    vm.controlflow_stack_mut().push(here);
+    Ok(())
+}
+
+fn loop_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let body = pop_controlflow(vm)?;
+    vm.compile_instruction(Instruction::CallPrimitive(rt_loop));
+    vm.compile_branch(body);
+    vm.compile_instruction(Instruction::CallPrimitive(rt_loop_end));
+    Ok(())
+}
+
+fn index_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = Rc::clone(
+        vm.env_stack()
+            .pick(0)
+            .map_err(VmErrorReason::EnvStackAccessError)?,
+    );
+    vm.data_stack_mut().push(v);
+    Ok(())
+}
+
+/// runtime part of `do`: move limit and index to the environment stack
+fn rt_do<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let index = util::pop(vm)?;
+    let limit = util::pop(vm)?;
+    vm.env_stack_mut().push(limit);
+    vm.env_stack_mut().push(index);
+    Ok(())
+}
+
+/// runtime part of `loop`: advance the index and push the loop flag
+fn rt_loop<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let index = vm
+        .env_stack_mut()
+        .pop()
+        .map_err(VmErrorReason::EnvStackAccessError)?;
+    let limit = Rc::clone(
+        vm.env_stack()
+            .pick(0)
+            .map_err(VmErrorReason::EnvStackAccessError)?,
+    );
+    match (index.as_ref(), limit.as_ref()) {
+        (Value::IntValue(index), Value::IntValue(limit)) => {
+            let next = index.wrapping_add(1);
+            vm.env_stack_mut().push(Rc::new(Value::IntValue(next)));
+            util::push_bool(vm, next < *limit);
+            Ok(())
+        }
+        _ => Err(VmErrorReason::TypeMismatchError("int")),
+    }
+}
+
+/// runtime part of `loop`: drop limit and index
+fn rt_loop_end<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    vm.env_stack_mut()
+        .remove(2)
+        .map_err(VmErrorReason::EnvStackAccessError)
+}
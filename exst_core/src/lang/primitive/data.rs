@@ -0,0 +1,248 @@
+//! data buffer words
+//!
+//! The data buffer is the linear memory of a program. `create`
+//! defines a word that pushes the address of the buffer top at
+//! definition time; `allot` and `,` grow the buffer.
+
+use super::util;
+use crate::lang::vm::buffer::DataAddress;
+use crate::lang::vm::value::Value;
+use crate::lang::vm::Instruction;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>) {
+    vm.define_primitive_word("create", false, "\"name\" -- : define a data word", create);
+    vm.define_primitive_word("allot", false, "n -- : extend the data buffer", allot);
+    vm.define_primitive_word(
+        "allot-init",
+        false,
+        "value count -- addr : allocate count cells filled with value",
+        allot_init,
+    );
+    vm.define_primitive_word(",", false, "x -- : append to the data buffer", comma);
+    vm.define_primitive_word(
+        "create-typed",
+        false,
+        "type-id \"name\" -- : define a one-cell data word accepting one type",
+        create_typed,
+    );
+    vm.define_primitive_word("@", false, "addr -- x : load a cell", fetch);
+    vm.define_primitive_word("!", false, "x addr -- : store into a cell", store);
+    vm.define_primitive_word(
+        "fill",
+        false,
+        "addr count value -- : store value into count consecutive cells",
+        fill,
+    );
+    vm.define_primitive_word(
+        "move",
+        false,
+        "src dst count -- : copy a run of cells, overlap safe",
+        move_cells,
+    );
+    vm.define_pure_primitive_word(
+        "cells",
+        false,
+        "n -- n' : size of n cells; identity here, kept for portability",
+        cells,
+    );
+    vm.define_pure_primitive_word("cell+", false, "addr -- addr' : next cell address", cell_plus);
+    vm.define_pure_primitive_word(
+        "+addr",
+        false,
+        "addr n -- addr' : advance an address by n cells",
+        plus_addr,
+    );
+    vm.define_primitive_word("ddp", false, "-- addr : current data buffer top", ddp);
+    vm.define_primitive_word(
+        "constant",
+        false,
+        "x \"name\" -- : define a constant word",
+        constant,
+    );
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    "
+    : variable create 1 allot ;
+    0 constant int-type
+    1 constant float-type
+    2 constant str-type
+    "
+}
+
+fn create<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    let address = vm.data_buffer().here();
+    vm.define_word_with_instructions(
+        &name,
+        false,
+        "-- addr",
+        vec![Instruction::Push(Rc::new(Value::DataAddress(address)))],
+    );
+    Ok(())
+}
+
+fn create_typed<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let type_id = u8::try_from(util::pop_int(vm)?)
+        .map_err(|_| VmErrorReason::TypeMismatchError("type id"))?;
+    let name = vm.next_symbol_token()?;
+    let address = vm.data_buffer().here();
+    vm.data_buffer_mut().allocate(1);
+    vm.register_typed_cell(address, i32::from(type_id));
+    vm.define_word_with_instructions(
+        &name,
+        false,
+        "-- addr",
+        vec![Instruction::Push(Rc::new(Value::DataAddress(address)))],
+    );
+    Ok(())
+}
+
+fn allot<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let n = util::pop_int(vm)?;
+    let n = usize::try_from(n).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    vm.data_buffer_mut().allocate(n);
+    Ok(())
+}
+
+fn comma<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = util::pop(vm)?;
+    vm.data_buffer_mut().push(v);
+    Ok(())
+}
+
+fn fetch<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let address = util::pop_data_address(vm)?;
+    let v = vm
+        .data_buffer()
+        .get(address)
+        .map_err(VmErrorReason::DataBufferAccessError)?;
+    vm.data_stack_mut().push(v);
+    Ok(())
+}
+
+fn store<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let address = util::pop_data_address(vm)?;
+    let v = util::pop(vm)?;
+    if let Some(expected) = vm.typed_cell_type(address) {
+        if v.type_id() != expected {
+            return Err(VmErrorReason::TypeMismatchError("value matching the cell type"));
+        }
+    }
+    vm.data_buffer_mut()
+        .set(address, v)
+        .map_err(VmErrorReason::DataBufferAccessError)?;
+    Ok(())
+}
+
+/// copy one cell of the data buffer
+fn copy_cell<T, E>(vm: &mut Vm<T, E>, from: usize, to: usize) -> Result<(), VmErrorReason<E>> {
+    let v = vm
+        .data_buffer()
+        .get(DataAddress::from_index(from))
+        .map_err(VmErrorReason::DataBufferAccessError)?;
+    vm.data_buffer_mut()
+        .set(DataAddress::from_index(to), v)
+        .map_err(VmErrorReason::DataBufferAccessError)?;
+    Ok(())
+}
+
+/// store a value into `count` consecutive cells starting at `base`
+fn fill_cells<T, E>(
+    vm: &mut Vm<T, E>,
+    base: usize,
+    count: usize,
+    v: &Rc<Value<T>>,
+) -> Result<(), VmErrorReason<E>> {
+    for i in 0..count {
+        vm.data_buffer_mut()
+            .set(DataAddress::from_index(base + i), Rc::clone(v))
+            .map_err(VmErrorReason::DataBufferAccessError)?;
+    }
+    Ok(())
+}
+
+fn fill<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = util::pop(vm)?;
+    let count = util::pop_int(vm)?;
+    let count =
+        usize::try_from(count).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let address = util::pop_data_address(vm)?;
+    let base = usize::try_from(address)?;
+    fill_cells(vm, base, count, &v)
+}
+
+fn allot_init<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let count = util::pop_int(vm)?;
+    let count =
+        usize::try_from(count).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let v = util::pop(vm)?;
+    let address = vm.data_buffer().here();
+    vm.data_buffer_mut().allocate(count);
+    let base = usize::try_from(address)?;
+    fill_cells(vm, base, count, &v)?;
+    util::push_value(vm, Value::DataAddress(address));
+    Ok(())
+}
+
+fn move_cells<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let count = util::pop_int(vm)?;
+    let count =
+        usize::try_from(count).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let dst = usize::try_from(util::pop_data_address(vm)?)?;
+    let src = usize::try_from(util::pop_data_address(vm)?)?;
+    if dst <= src {
+        for i in 0..count {
+            copy_cell(vm, src + i, dst + i)?;
+        }
+    } else {
+        // copy backward so an overlapping destination never reads
+        // cells it already overwrote
+        for i in (0..count).rev() {
+            copy_cell(vm, src + i, dst + i)?;
+        }
+    }
+    Ok(())
+}
+
+fn cells<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    // every value occupies one logical cell, so this only checks the
+    // operand is a count
+    let n = util::pop_int(vm)?;
+    util::push_int(vm, n);
+    Ok(())
+}
+
+fn cell_plus<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let address = util::pop_data_address(vm)?;
+    util::push_value(vm, Value::DataAddress(address.next()));
+    Ok(())
+}
+
+fn plus_addr<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let n = util::pop_int(vm)?;
+    let n = usize::try_from(n).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let address = util::pop_data_address(vm)?;
+    let base = usize::try_from(address)?;
+    util::push_value(vm, Value::DataAddress(DataAddress::from_index(base + n)));
+    Ok(())
+}
+
+fn ddp<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let address = vm.data_buffer().here();
+    util::push_value(vm, Value::DataAddress(address));
+    Ok(())
+}
+
+fn constant<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = util::pop(vm)?;
+    let name = vm.next_symbol_token()?;
+    vm.define_word_with_instructions(&name, false, "-- x", vec![Instruction::Push(v)]);
+    Ok(())
+}
@@ -0,0 +1,227 @@
+//! debugging and introspection words
+
+use super::util;
+use crate::lang::vm::dump;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use crate::lang::vm::VmExecutionState;
+use crate::lang::vm::VmState;
+use crate::lang::VmInt;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>)
+where
+    T: fmt::Debug + fmt::Display,
+    E: fmt::Debug,
+{
+    vm.define_primitive_word("see", false, "\"name\" -- : dump the code of a word", see);
+    vm.define_primitive_word(
+        "see-range",
+        false,
+        "from to -- : dump the instructions between two addresses",
+        see_range,
+    );
+    vm.define_primitive_word(
+        "dump-data",
+        false,
+        "addr count -- : dump data buffer cells",
+        dump_data,
+    );
+    vm.define_primitive_word(
+        "where",
+        false,
+        "\"name\" -- : print the source position of each instruction of a word",
+        where_word,
+    );
+    vm.define_primitive_word(
+        "word-size",
+        false,
+        "\"name\" -- n : number of compiled instructions of a word",
+        word_size,
+    );
+    vm.define_primitive_word("words", false, "-- : list all defined words", words);
+    vm.define_primitive_word(
+        "locals",
+        true,
+        "-- : print the locals declared in the current definition",
+        locals,
+    );
+    vm.define_primitive_word(".s", false, "-- : dump the data stack", dump_stack);
+    vm.define_primitive_word(
+        "backtrace",
+        false,
+        "-- : print the call chain, innermost caller first",
+        backtrace,
+    );
+    vm.define_primitive_word(
+        "dump-peak",
+        false,
+        "-- : print the peak stack depths",
+        dump_peak,
+    );
+    vm.define_primitive_word(
+        "state?",
+        false,
+        "-- n : 0 interpreting, 1 compiling, 2 recursable compiling",
+        state_query,
+    );
+    vm.define_primitive_word(
+        "exec-state?",
+        false,
+        "-- n : 0 running, 1 finishing",
+        exec_state_query,
+    );
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    ""
+}
+
+fn see<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: fmt::Debug,
+    E: fmt::Debug,
+{
+    let name = vm.next_symbol_token()?;
+    let mut out = String::new();
+    dump::dump_word_code(vm, &name, &mut |line| {
+        out.push_str(line);
+        out.push('\n');
+    })?;
+    vm.resources().write_stdout(&out)?;
+    Ok(())
+}
+
+fn see_range<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: fmt::Debug,
+    E: fmt::Debug,
+{
+    let to = util::pop_code_address(vm)?;
+    let from = util::pop_code_address(vm)?;
+    let mut out = String::new();
+    dump::dump_code_range(vm, from, to, &mut |line| {
+        out.push_str(line);
+        out.push('\n');
+    });
+    vm.resources().write_stdout(&out)?;
+    Ok(())
+}
+
+fn where_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    let mut out = String::new();
+    dump::dump_word_source(vm, &name, &mut |line| {
+        out.push_str(line);
+        out.push('\n');
+    })?;
+    vm.resources().write_stdout(&out)?;
+    Ok(())
+}
+
+fn word_size<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    let count = dump::word_instruction_count(vm, &name)?;
+    util::push_int(vm, count as VmInt);
+    Ok(())
+}
+
+fn state_query<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let code = match vm.state() {
+        VmState::Interpretation => 0,
+        VmState::Compilation => 1,
+        VmState::RecursableCompilation => 2,
+    };
+    util::push_int(vm, code);
+    Ok(())
+}
+
+fn exec_state_query<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let code = match vm.exec_state() {
+        VmExecutionState::NormalState => 0,
+        VmExecutionState::FinishState => 1,
+    };
+    util::push_int(vm, code);
+    Ok(())
+}
+
+fn dump_peak<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let out = format!(
+        "data stack peak: {}\nreturn stack peak: {}\nenv stack peak: {}\n",
+        vm.data_stack().peak(),
+        vm.return_stack().peak(),
+        vm.env_stack().peak()
+    );
+    vm.resources().write_stdout(&out)?;
+    Ok(())
+}
+
+fn dump_data<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: fmt::Display,
+{
+    let count = util::pop_int(vm)?;
+    let count =
+        usize::try_from(count).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let address = util::pop_data_address(vm)?;
+    let mut out = String::new();
+    dump::dump_data_buffer(vm, address, count, &mut |line| {
+        out.push_str(line);
+        out.push('\n');
+    });
+    vm.resources().write_stdout(&out)?;
+    Ok(())
+}
+
+fn words<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let names: Vec<String> = vm
+        .word_dictionary()
+        .all_word_names()
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect();
+    let mut out = names.join(" ");
+    out.push('\n');
+    vm.resources().write_stdout(&out)?;
+    Ok(())
+}
+
+fn locals<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let names = vm.local_dictionary().get_all_local_names().join(" ");
+    if !names.is_empty() {
+        vm.resources().write_stdout(&format!("{}\n", names))?;
+    }
+    Ok(())
+}
+
+fn dump_stack<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: fmt::Display,
+{
+    let mut out = String::new();
+    dump::dump_data_stack(vm, &mut |line| {
+        out.push_str(line);
+        out.push('\n');
+    });
+    vm.resources().write_stdout(&out)?;
+    Ok(())
+}
+
+fn backtrace<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let mut out = String::new();
+    // the top frame returns into the immediate caller, so walking the
+    // stack downwards lists the chain innermost first
+    for frame in vm.return_stack().iter().rev() {
+        let name = vm
+            .word_dictionary()
+            .guess_name(frame.return_address())
+            .map_or(String::from("?"), |(name, _)| name.clone());
+        out.push_str(&name);
+        out.push('\n');
+    }
+    vm.resources().write_stdout(&out)?;
+    Ok(())
+}
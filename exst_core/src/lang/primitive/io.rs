@@ -0,0 +1,88 @@
+//! input/output words
+
+use super::util;
+use crate::lang::tokenizer::ValueToken;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use crate::lang::VmInt;
+use std::fmt;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>)
+where
+    T: fmt::Display,
+{
+    vm.define_primitive_word(".", false, "x -- : print the top of the stack", dot);
+    vm.define_primitive_word("cr", false, "-- : print a line feed", cr);
+    vm.define_primitive_word("emit", false, "n -- : print the character n", emit);
+    vm.define_primitive_word(
+        "slurp-ints",
+        false,
+        "name -- n1 .. nk k : push every integer token of a resource",
+        slurp_ints,
+    );
+    vm.define_primitive_word(
+        "now-ms",
+        false,
+        "-- n : milliseconds since the epoch, truncated to an int",
+        now_ms,
+    );
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    "
+    : space 32 emit ;
+    : spaces 0 do space loop ;
+    : time now-ms >r exec now-ms r> - ;
+    "
+}
+
+fn dot<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: fmt::Display,
+{
+    let v = util::pop(vm)?;
+    let text = format!("{} ", v);
+    vm.resources().write_stdout(&text)?;
+    Ok(())
+}
+
+fn cr<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    vm.resources().write_stdout("\n")?;
+    Ok(())
+}
+
+fn emit<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = util::pop(vm)?;
+    let c = v
+        .try_into_char()
+        .ok_or(VmErrorReason::TypeMismatchError("char"))?;
+    vm.resources().write_stdout(&c.to_string())?;
+    Ok(())
+}
+
+fn slurp_ints<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = util::pop_str(vm)?;
+    let mut stream = vm.resources().get_token_iterator(&name)?;
+    let mut count: VmInt = 0;
+    while let Some(token) = stream.next_token()? {
+        if let ValueToken::IntValue(i) = token.value_token {
+            util::push_int(vm, i);
+            count += 1;
+        }
+    }
+    util::push_int(vm, count);
+    Ok(())
+}
+
+fn now_ms<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    util::push_int(vm, ms as VmInt);
+    Ok(())
+}
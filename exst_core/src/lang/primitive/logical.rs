@@ -0,0 +1,202 @@
+//! comparison and bitwise words
+//!
+//! Comparisons use the total order on `Value`, so values of different
+//! variants compare by their variant rank. Flags are the integers one
+//! and zero.
+
+use super::util;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use crate::lang::VmInt;
+use std::cmp::Ordering;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>)
+where
+    T: Ord,
+{
+    vm.define_pure_primitive_word("=", false, "a b -- flag : a = b", eq);
+    vm.define_pure_primitive_word("<>", false, "a b -- flag : a <> b", ne);
+    vm.define_pure_primitive_word("<", false, "a b -- flag : a < b", lt);
+    vm.define_pure_primitive_word("<=", false, "a b -- flag : a <= b", le);
+    vm.define_pure_primitive_word(">", false, "a b -- flag : a > b", gt);
+    vm.define_pure_primitive_word(">=", false, "a b -- flag : a >= b", ge);
+    vm.define_pure_primitive_word("not", false, "flag -- flag' : logical negation", not);
+    vm.define_pure_primitive_word("and", false, "a b -- c : bitwise and", and);
+    vm.define_pure_primitive_word("or", false, "a b -- c : bitwise or", or);
+    vm.define_pure_primitive_word("xor", false, "a b -- c : bitwise xor", xor);
+    vm.define_pure_primitive_word("invert", false, "a -- b : bitwise complement", invert);
+    vm.define_pure_primitive_word("lshift", false, "a u -- b : shift left by u bits", lshift);
+    vm.define_pure_primitive_word(
+        "rshift",
+        false,
+        "a u -- b : logical shift right by u bits",
+        rshift,
+    );
+    vm.define_pure_primitive_word(
+        "arshift",
+        false,
+        "a u -- b : arithmetic shift right by u bits; the sign bit \
+         fills from the left, where rshift fills with zeros",
+        arshift,
+    );
+    vm.define_pure_primitive_word("rol", false, "a u -- b : rotate left by u bits", rol);
+    vm.define_pure_primitive_word("ror", false, "a u -- b : rotate right by u bits", ror);
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    "
+    : true 1 ;
+    : false 0 ;
+    : 0= 0 = ;
+    : 0<> 0 <> ;
+    : 0< 0 < ;
+    : 0> 0 > ;
+    "
+}
+
+/// pop two values and push the flag of the comparison
+///
+/// In strict compare mode operands of different variants are a type
+/// mismatch instead of comparing by variant rank.
+fn compare<T, E>(
+    vm: &mut Vm<T, E>,
+    f: fn(Ordering) -> bool,
+) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    let b = util::pop(vm)?;
+    let a = util::pop(vm)?;
+    if vm.strict_compare() && a.type_id() != b.type_id() {
+        return Err(VmErrorReason::TypeMismatchError(
+            "operands of the same type",
+        ));
+    }
+    util::push_bool(vm, f(a.cmp(&b)));
+    Ok(())
+}
+
+fn eq<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    compare(vm, |o| o == Ordering::Equal)
+}
+
+fn ne<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    compare(vm, |o| o != Ordering::Equal)
+}
+
+fn lt<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    compare(vm, |o| o == Ordering::Less)
+}
+
+fn le<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    compare(vm, |o| o != Ordering::Greater)
+}
+
+fn gt<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    compare(vm, |o| o == Ordering::Greater)
+}
+
+fn ge<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: Ord,
+{
+    compare(vm, |o| o != Ordering::Less)
+}
+
+fn not<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = util::pop(vm)?;
+    util::push_bool(vm, !v.is_true());
+    Ok(())
+}
+
+fn and<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let b = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    util::push_int(vm, a & b);
+    Ok(())
+}
+
+fn or<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let b = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    util::push_int(vm, a | b);
+    Ok(())
+}
+
+fn xor<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let b = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    util::push_int(vm, a ^ b);
+    Ok(())
+}
+
+fn invert<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let a = util::pop_int(vm)?;
+    util::push_int(vm, !a);
+    Ok(())
+}
+
+fn lshift<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let u = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    util::push_int(vm, a.wrapping_shl(u as u32));
+    Ok(())
+}
+
+fn rshift<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let u = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    #[cfg(not(feature = "int64"))]
+    let shifted = (a as u32).wrapping_shr(u as u32) as VmInt;
+    #[cfg(feature = "int64")]
+    let shifted = (a as u64).wrapping_shr(u as u32) as VmInt;
+    util::push_int(vm, shifted);
+    Ok(())
+}
+
+fn arshift<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let u = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    // a signed shift keeps the sign, unlike the logical `rshift`
+    util::push_int(vm, a.wrapping_shr(u as u32));
+    Ok(())
+}
+
+fn rol<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let u = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    #[cfg(not(feature = "int64"))]
+    let rotated = (a as u32).rotate_left(u as u32) as VmInt;
+    #[cfg(feature = "int64")]
+    let rotated = (a as u64).rotate_left(u as u32) as VmInt;
+    util::push_int(vm, rotated);
+    Ok(())
+}
+
+fn ror<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let u = util::pop_int(vm)?;
+    let a = util::pop_int(vm)?;
+    #[cfg(not(feature = "int64"))]
+    let rotated = (a as u32).rotate_right(u as u32) as VmInt;
+    #[cfg(feature = "int64")]
+    let rotated = (a as u64).rotate_right(u as u32) as VmInt;
+    util::push_int(vm, rotated);
+    Ok(())
+}
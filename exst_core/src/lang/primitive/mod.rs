@@ -0,0 +1,1244 @@
+//! primitive words
+//!
+//! Each submodule registers a family of words with `initialize` and
+//! contributes a preload script with words defined in the language
+//! itself. `initialize` in this module wires up everything.
+
+pub mod util;
+pub mod arithmetic;
+pub mod logical;
+pub mod stack;
+pub mod data;
+pub mod controls;
+pub mod string;
+pub mod io;
+pub mod word;
+pub mod debug;
+
+use super::tokenizer::new_token_stream_from_string;
+use super::vm::Vm;
+use super::vm::VmErrorReason;
+use std::fmt;
+
+/// register every primitive word and run the preload scripts
+pub fn initialize<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>>
+where
+    T: fmt::Debug + fmt::Display + Ord,
+    E: fmt::Debug,
+{
+    // every primitive and preload definition compiles a handful of
+    // instructions; pre-size the code buffer to avoid reallocations
+    vm.code_buffer_mut().reserve(1024);
+    arithmetic::initialize(vm);
+    logical::initialize(vm);
+    stack::initialize(vm);
+    data::initialize(vm);
+    controls::initialize(vm);
+    string::initialize(vm);
+    io::initialize(vm);
+    word::initialize(vm);
+    debug::initialize(vm);
+    preload(vm, word::preload_script())?;
+    preload(vm, arithmetic::preload_script())?;
+    preload(vm, logical::preload_script())?;
+    preload(vm, stack::preload_script())?;
+    preload(vm, data::preload_script())?;
+    preload(vm, controls::preload_script())?;
+    preload(vm, string::preload_script())?;
+    preload(vm, io::preload_script())?;
+    preload(vm, debug::preload_script())?;
+    Ok(())
+}
+
+/// the combined preload source of every module, in the order
+/// `initialize` runs it
+///
+/// Useful for inspecting the standard library or re-embedding it
+/// into another host.
+pub fn standard_library() -> String {
+    [
+        word::preload_script(),
+        arithmetic::preload_script(),
+        logical::preload_script(),
+        stack::preload_script(),
+        data::preload_script(),
+        controls::preload_script(),
+        string::preload_script(),
+        io::preload_script(),
+        debug::preload_script(),
+    ]
+    .concat()
+}
+
+/// run a preload script on the machine
+///
+/// This is the same mechanism `initialize` uses for the standard
+/// library, exposed so an embedder can bootstrap its own word
+/// libraries:
+///
+/// ```
+/// use exst_core::lang::resource::BufferResources;
+/// use exst_core::lang::vm::Vm;
+/// use exst_core::lang::primitive;
+///
+/// let mut vm: Vm<i32, i32> = Vm::new(BufferResources::new());
+/// primitive::initialize(&mut vm).unwrap();
+/// primitive::preload(&mut vm, ": double 2 * ;").unwrap();
+/// ```
+pub fn preload<T, E>(vm: &mut Vm<T, E>, script: &'static str) -> Result<(), VmErrorReason<E>> {
+    vm.call_script(Box::new(new_token_stream_from_string(
+        String::from(script),
+        String::from("<preload>"),
+    )));
+    vm.exec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::resource::BufferResources;
+    use crate::lang::vm::value::Value;
+    use std::rc::Rc;
+
+    type TestVm = Vm<i32, i32>;
+
+    fn new_test_vm() -> (TestVm, Rc<BufferResources>) {
+        let resources = Rc::new(BufferResources::new());
+        let mut vm = Vm::new(Rc::clone(&resources));
+        initialize(&mut vm).unwrap();
+        (vm, resources)
+    }
+
+    fn run(vm: &mut TestVm, script: &str) -> Result<(), VmErrorReason<i32>> {
+        vm.call_script(Box::new(
+            crate::lang::tokenizer::new_token_stream_from_string(
+                String::from(script),
+                String::from("test"),
+            ),
+        ));
+        vm.exec()
+    }
+
+    fn pop_int(vm: &mut TestVm) -> crate::lang::VmInt {
+        match *vm.data_stack_mut().pop().unwrap() {
+            Value::IntValue(i) => i,
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_standard_library() {
+        let text = standard_library();
+        assert!(text.contains(": variable"));
+        assert!(text.contains(": true"));
+        // the combined text compiles on a fresh machine
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, &text).unwrap();
+        assert_eq!(vm.data_stack().here(), 0);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "1 2 + 3 *").unwrap();
+        assert_eq!(pop_int(&mut vm), 9);
+        run(&mut vm, "7 2 / 7 2 mod").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), 3);
+        match run(&mut vm, "1 0 /") {
+            Err(VmErrorReason::ZeroDivisionError) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "int64"))]
+    fn test_checked_arithmetic() {
+        let (mut vm, _) = new_test_vm();
+        // the plain forms wrap at the boundary
+        run(&mut vm, "2147483647 1 +").unwrap();
+        assert_eq!(pop_int(&mut vm), i32::MIN);
+        // the checked forms report the overflow instead
+        run(&mut vm, "2147483647 1 +?").unwrap();
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 0);
+        run(&mut vm, "2 3 +? 7 5 -? 2147483647 2 *?").unwrap();
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), 5);
+    }
+
+    #[test]
+    #[cfg(feature = "int64")]
+    fn test_wide_int_round_trip() {
+        let (mut vm, _) = new_test_vm();
+        // a literal beyond the i32 range survives tokenize, push and pop
+        run(&mut vm, "4294967296 1 +").unwrap();
+        assert_eq!(pop_int(&mut vm), 4294967297);
+        // the checked forms overflow at the i64 boundary instead
+        run(&mut vm, "9223372036854775807 1 +?").unwrap();
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 0);
+    }
+
+    fn pop_float(vm: &mut TestVm) -> f64 {
+        match *vm.data_stack_mut().pop().unwrap() {
+            Value::FloatValue(f) => f,
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_numeric_casts() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "3 >float").unwrap();
+        assert_eq!(pop_float(&mut vm), 3.0);
+        run(&mut vm, "2.7 >int -2.7 >int").unwrap();
+        assert_eq!(pop_int(&mut vm), -2);
+        assert_eq!(pop_int(&mut vm), 2);
+        run(&mut vm, "2.5 floor 2.5 ceil 2.4 round").unwrap();
+        assert_eq!(pop_float(&mut vm), 2.0);
+        assert_eq!(pop_float(&mut vm), 3.0);
+        assert_eq!(pop_float(&mut vm), 2.0);
+        match run(&mut vm, "1.0e30 >int") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // `VmInt::MAX as f64` rounds up past the range under int64;
+        // the first float at or above 2^(width-1) must error too
+        let just_above = -(crate::lang::VmInt::MIN as f64);
+        match run(&mut vm, &format!("{:.1} >int", just_above)) {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // while a large value inside the range still converts
+        run(&mut vm, &format!("{:.1} >int", just_above / 2.0)).unwrap();
+        assert!(pop_int(&mut vm) > 0);
+    }
+
+    #[test]
+    fn test_int_base_conversion() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "\"ff\" 16 >int-base").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), 255);
+        run(&mut vm, "\"ff\" 10 >int-base").unwrap();
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 0);
+        run(&mut vm, "\"-101\" 2 >int-base").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), -5);
+        match run(&mut vm, "\"1\" 40 >int-base") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_comparison() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "1 2 < 2 1 < 1 1 =").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_shift_rotate() {
+        let (mut vm, _) = new_test_vm();
+        // the arithmetic shift keeps the sign, the rotates wrap the
+        // shifted-out bit around to the other end
+        run(&mut vm, "-8 1 arshift 1 1 ror 1 rol 1 3 rol 3 ror").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), -4);
+    }
+
+    #[test]
+    fn test_strict_compare() {
+        let (mut vm, _) = new_test_vm();
+        // the lenient default compares mixed variants by rank
+        run(&mut vm, "1 \"a\" <").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        vm.set_strict_compare(true);
+        match run(&mut vm, "1 \"a\" <") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // same variant operands still compare
+        run(&mut vm, "1 2 <").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_stack_words() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "1 2 dup depth").unwrap();
+        assert_eq!(pop_int(&mut vm), 3);
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+        run(&mut vm, "10 20 swap").unwrap();
+        assert_eq!(pop_int(&mut vm), 10);
+        assert_eq!(pop_int(&mut vm), 20);
+    }
+
+    #[test]
+    fn test_sdup_and_clone_value() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "\"abc\" sdup").unwrap();
+        let copy = vm.data_stack_mut().pop().unwrap();
+        let original = vm.data_stack_mut().pop().unwrap();
+        assert!(!Rc::ptr_eq(&copy, &original));
+        assert_eq!(*copy, *original);
+        run(&mut vm, "\"xyz\" dup clone-value").unwrap();
+        let copy = vm.data_stack_mut().pop().unwrap();
+        let original = vm.data_stack_mut().pop().unwrap();
+        assert!(!Rc::ptr_eq(&copy, &original));
+        assert_eq!(*copy, *original);
+        match run(&mut vm, "1 sdup") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    fn pop_str(vm: &mut TestVm) -> String {
+        match &*vm.data_stack_mut().pop().unwrap() {
+            Value::StrValue(s) => s.clone(),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "\"a,b,,c\" 44 split").unwrap();
+        assert_eq!(pop_int(&mut vm), 4);
+        assert_eq!(pop_str(&mut vm), "c");
+        assert_eq!(pop_str(&mut vm), "");
+        assert_eq!(pop_str(&mut vm), "b");
+        assert_eq!(pop_str(&mut vm), "a");
+        match run(&mut vm, "\"a\" \"b\" split") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match run(&mut vm, "1 44 split") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_join() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "\"a\" \"b\" \"c\" 3 \"-\" join").unwrap();
+        assert_eq!(pop_str(&mut vm), "a-b-c");
+        run(&mut vm, "0 \"-\" join").unwrap();
+        assert_eq!(pop_str(&mut vm), "");
+        match run(&mut vm, "\"a\" 1 1 \"-\" join") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match run(&mut vm, "-1 \"-\" join") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_colon_definition() {
+        let (mut vm, resources) = new_test_vm();
+        run(&mut vm, ": sq dup * ; 4 sq .").unwrap();
+        assert_eq!(resources.stdout(), "16 ");
+    }
+
+    #[test]
+    fn test_if_else() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": t if 10 else 20 endif ; 1 t 0 t").unwrap();
+        assert_eq!(pop_int(&mut vm), 20);
+        assert_eq!(pop_int(&mut vm), 10);
+    }
+
+    #[test]
+    fn test_do_loop() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": c 0 3 0 do 1 + loop ; c").unwrap();
+        assert_eq!(pop_int(&mut vm), 3);
+        run(&mut vm, ": is 0 4 0 do i + loop ; is").unwrap();
+        assert_eq!(pop_int(&mut vm), 6);
+    }
+
+    #[test]
+    fn test_begin_until() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": c 0 begin 1+ dup 5 >= until ; c").unwrap();
+        assert_eq!(pop_int(&mut vm), 5);
+    }
+
+    #[test]
+    fn test_times() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": inc1 1 + ; 0 ' inc1 5 times").unwrap();
+        assert_eq!(pop_int(&mut vm), 5);
+        run(&mut vm, "7 ' inc1 0 times").unwrap();
+        assert_eq!(pop_int(&mut vm), 7);
+        run(&mut vm, "7 ' inc1 -3 times").unwrap();
+        assert_eq!(pop_int(&mut vm), 7);
+    }
+
+    #[test]
+    fn test_each_int() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "0 ' + 2 5 each-int").unwrap();
+        assert_eq!(pop_int(&mut vm), 9);
+        run(&mut vm, "1 ' + 5 5 each-int").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_locals() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": f local a local b a b - ; 10 4 f").unwrap();
+        assert_eq!(pop_int(&mut vm), -6);
+    }
+
+    #[test]
+    fn test_variable() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "variable x 42 x ! x @").unwrap();
+        assert_eq!(pop_int(&mut vm), 42);
+    }
+
+    #[test]
+    fn test_exec_with_typed_args() {
+        let (mut vm, _) = new_test_vm();
+        vm.call_script(Box::new(
+            crate::lang::tokenizer::new_token_stream_from_string(
+                String::from("0 arg 1 arg"),
+                String::from("test"),
+            ),
+        ));
+        vm.exec_with_typed_args(vec![
+            Rc::new(Value::IntValue(7)),
+            Rc::new(Value::StrValue(String::from("x"))),
+        ])
+        .unwrap();
+        match vm.data_stack_mut().pop().unwrap().as_ref() {
+            Value::StrValue(s) => assert_eq!(s, "x"),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        assert_eq!(pop_int(&mut vm), 7);
+    }
+
+    #[test]
+    fn test_stack_save_restore() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "variable h 1 2 3 stack-save h ! 99 88 drop h @ stack-restore").unwrap();
+        assert_eq!(vm.data_stack().here(), 3);
+        assert_eq!(pop_int(&mut vm), 3);
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+        match run(&mut vm, "7 stack-restore") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    fn pop_data_address_index(vm: &mut TestVm) -> usize {
+        use std::convert::TryFrom;
+        match *vm.data_stack_mut().pop().unwrap() {
+            Value::DataAddress(a) => usize::try_from(a).unwrap(),
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    fn data_cell(vm: &TestVm, index: usize) -> crate::lang::VmInt {
+        use crate::lang::vm::buffer::DataAddress;
+        match *vm.data_buffer().get(DataAddress::from_index(index)).unwrap() {
+            Value::IntValue(i) => i,
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    fn push_data_address(vm: &mut TestVm, index: usize) {
+        use crate::lang::vm::buffer::DataAddress;
+        vm.data_stack_mut()
+            .push(Rc::new(Value::DataAddress(DataAddress::from_index(index))));
+    }
+
+    #[test]
+    fn test_address_arithmetic() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "create x 3 allot 42 x 2 +addr ! x 2 +addr @").unwrap();
+        assert_eq!(pop_int(&mut vm), 42);
+        run(&mut vm, "x cell+ cell+ x 2 cells +addr").unwrap();
+        let a = pop_data_address_index(&mut vm);
+        let b = pop_data_address_index(&mut vm);
+        assert_eq!(a, b);
+        run(&mut vm, "x").unwrap();
+        assert_eq!(a, pop_data_address_index(&mut vm) + 2);
+    }
+
+    #[test]
+    fn test_dump_data() {
+        let (mut vm, resources) = new_test_vm();
+        run(&mut vm, "create a 2 allot 7 a ! a 100 dump-data").unwrap();
+        let out = resources.stdout();
+        assert!(out.contains("int"));
+        assert!(out.contains('7'));
+        // the dump stops at the buffer end instead of erroring
+        assert_eq!(out.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_allot_init() {
+        use crate::lang::vm::buffer::DataAddress;
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "ddp 42 3 allot-init").unwrap();
+        let base = pop_data_address_index(&mut vm);
+        assert_eq!(pop_data_address_index(&mut vm), base);
+        assert_eq!(data_cell(&vm, base), 42);
+        assert_eq!(data_cell(&vm, base + 1), 42);
+        assert_eq!(data_cell(&vm, base + 2), 42);
+        assert_eq!(vm.data_buffer().here(), DataAddress::from_index(base + 3));
+    }
+
+    #[test]
+    fn test_fill_and_move() {
+        use crate::lang::vm::buffer::DataAddress;
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "create a 8 allot a 3 7 fill a").unwrap();
+        let base = pop_data_address_index(&mut vm);
+        assert_eq!(data_cell(&vm, base), 7);
+        assert_eq!(data_cell(&vm, base + 2), 7);
+        assert_eq!(data_cell(&vm, base + 3), 0);
+        for (i, v) in [1, 2, 3].iter().enumerate() {
+            vm.data_buffer_mut()
+                .set(DataAddress::from_index(base + i), Rc::new(Value::IntValue(*v)))
+                .unwrap();
+        }
+        // non-overlapping copy
+        push_data_address(&mut vm, base);
+        push_data_address(&mut vm, base + 4);
+        run(&mut vm, "3 move").unwrap();
+        assert_eq!(
+            (data_cell(&vm, base + 4), data_cell(&vm, base + 5), data_cell(&vm, base + 6)),
+            (1, 2, 3)
+        );
+        // overlapping copy one cell forward; a naive forward loop
+        // would smear the first value over the whole range
+        push_data_address(&mut vm, base);
+        push_data_address(&mut vm, base + 1);
+        run(&mut vm, "3 move").unwrap();
+        assert_eq!(
+            (data_cell(&vm, base), data_cell(&vm, base + 1), data_cell(&vm, base + 2), data_cell(&vm, base + 3)),
+            (1, 1, 2, 3)
+        );
+        // out of range accesses are reported
+        push_data_address(&mut vm, base + 1000);
+        push_data_address(&mut vm, base);
+        match run(&mut vm, "1 move") {
+            Err(VmErrorReason::DataBufferAccessError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_ddp() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "ddp 1 allot ddp").unwrap();
+        let after = pop_data_address_index(&mut vm);
+        let before = pop_data_address_index(&mut vm);
+        assert_eq!(after, before + 1);
+    }
+
+    #[test]
+    fn test_typed_cell() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "int-type create-typed x 42 x ! x @").unwrap();
+        assert_eq!(pop_int(&mut vm), 42);
+        match run(&mut vm, "\"oops\" x !") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // an untyped cell still accepts anything
+        run(&mut vm, "variable y \"ok\" y !").unwrap();
+    }
+
+    #[test]
+    fn test_typed_cell_dropped_on_rollback() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "marker m int-type create-typed x m").unwrap();
+        // the reused address carries no stale type constraint
+        run(&mut vm, "variable y \"ok\" y !").unwrap();
+    }
+
+    #[test]
+    fn test_word_size() {
+        let (mut vm, _) = new_test_vm();
+        // a one literal body compiles to push, return and terminator
+        run(&mut vm, ": w 1 ; word-size w").unwrap();
+        assert_eq!(pop_int(&mut vm), 3);
+        match run(&mut vm, "word-size missing") {
+            Err(VmErrorReason::UndefinedWord(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_redefinition_check() {
+        use crate::lang::vm::RedefinitionCheckMode;
+        let (mut vm, resources) = new_test_vm();
+        // silent by default
+        run(&mut vm, ": foo 1 ; : foo 2 ;").unwrap();
+        assert_eq!(resources.stderr(), "");
+        vm.set_redefinition_check_mode(RedefinitionCheckMode::Warn);
+        run(&mut vm, ": foo 3 ; foo").unwrap();
+        assert_eq!(pop_int(&mut vm), 3);
+        assert_eq!(resources.stderr(), "redefinition of foo\n");
+        vm.set_redefinition_check_mode(RedefinitionCheckMode::Error);
+        match run(&mut vm, ": foo 4 ;") {
+            Err(VmErrorReason::WordError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_stack_check() {
+        use crate::lang::vm::StackCheckMode;
+        let (mut vm, _) = new_test_vm();
+        vm.set_stack_check_mode(StackCheckMode::Error);
+        match run(&mut vm, ": bad ( -- ) drop ;") {
+            Err(VmErrorReason::WordError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        vm.reset_execution();
+        run(&mut vm, ": ok ( a b -- c ) + ; 1 2 ok").unwrap();
+        assert_eq!(pop_int(&mut vm), 3);
+        // definitions without a declared effect are left alone
+        run(&mut vm, ": free drop drop ;").unwrap();
+    }
+
+    #[test]
+    fn test_unterminated_definition() {
+        let (mut vm, _) = new_test_vm();
+        match run(&mut vm, ": foo 1 2") {
+            Err(VmErrorReason::WordError(message)) => {
+                assert_eq!(message, "unterminated definition");
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // the dangling reservation is cleared, so defining works again
+        run(&mut vm, ": bar ; 5").unwrap();
+        assert_eq!(pop_int(&mut vm), 5);
+    }
+
+    #[test]
+    fn test_unbalanced_definition() {
+        let (mut vm, _) = new_test_vm();
+        match run(&mut vm, ": bad if 1 ;") {
+            Err(VmErrorReason::WordError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        vm.reset_execution();
+        run(&mut vm, ": ok if 1 endif ; 1 ok").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_vocabularies() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "vocabulary foo vocabulary bar").unwrap();
+        run(&mut vm, "also foo : greet 1 ; previous").unwrap();
+        run(&mut vm, "also bar : greet 2 ; previous").unwrap();
+        // the same name resolves by the active search order
+        run(&mut vm, "also foo greet previous also bar greet previous").unwrap();
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+        // global words stay reachable through the fallback
+        run(&mut vm, ": g 9 ; also foo g previous").unwrap();
+        assert_eq!(pop_int(&mut vm), 9);
+        match run(&mut vm, "also unknown") {
+            Err(VmErrorReason::WordError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_define_returns_code_address() {
+        fn noop(_: &mut TestVm) -> Result<(), VmErrorReason<i32>> {
+            Ok(())
+        }
+        let (mut vm, _) = new_test_vm();
+        let code = vm.define_primitive_word("noop", false, "--", noop);
+        run(&mut vm, "' noop").unwrap();
+        match *vm.data_stack_mut().pop().unwrap() {
+            Value::CodeAddress(a) => assert_eq!(a, code),
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_latest() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": foo ; latest ' foo =").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_make_immediate() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": w 1 ; make-immediate w immediate? w").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        // an immediate word runs while the definition compiles
+        run(&mut vm, ": v w ;").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        run(&mut vm, "v").unwrap();
+        assert_eq!(vm.data_stack().here(), 0);
+        // back to normal the call is compiled instead
+        run(&mut vm, "make-normal w immediate? w : u w ; u").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_int(&mut vm), 0);
+        match run(&mut vm, "make-immediate no-such-word") {
+            Err(VmErrorReason::UndefinedWord(name)) => assert_eq!(name, "no-such-word"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_tick_and_exec() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": sq dup * ; 3 ' sq exec").unwrap();
+        assert_eq!(pop_int(&mut vm), 9);
+    }
+
+    #[test]
+    fn test_noname() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ":noname 1 + ; 5 swap exec").unwrap();
+        assert_eq!(pop_int(&mut vm), 6);
+    }
+
+    #[test]
+    fn test_literal() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": five [ 2 3 + ] literal ; five").unwrap();
+        assert_eq!(pop_int(&mut vm), 5);
+    }
+
+    #[test]
+    fn test_literal_any_value() {
+        let (mut vm, _) = new_test_vm();
+        // immediate words feed compile time values to `literal`
+        run(&mut vm, ": inject-str \"abc\" ; make-immediate inject-str").unwrap();
+        run(&mut vm, ": inject-float 2.5 ; make-immediate inject-float").unwrap();
+        run(
+            &mut vm,
+            ": w inject-float literal inject-str literal ; w",
+        )
+        .unwrap();
+        assert_eq!(pop_str(&mut vm), "abc");
+        assert_eq!(pop_float(&mut vm), 2.5);
+    }
+
+    #[test]
+    fn test_recursable() {
+        let (mut vm, _) = new_test_vm();
+        run(
+            &mut vm,
+            ": fact recursable dup 1 > if dup 1- fact * endif ; 5 fact",
+        )
+        .unwrap();
+        assert_eq!(pop_int(&mut vm), 120);
+    }
+
+    #[test]
+    fn test_comment_word_documents() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": add2 + ; ( a b -- c )").unwrap();
+        let word = vm.word_dictionary().find_word("add2").unwrap();
+        assert_eq!(word.document(), "a b -- c ");
+    }
+
+    #[test]
+    fn test_doc_words() {
+        let (mut vm, resources) = new_test_vm();
+        run(&mut vm, ": add2 + ; ( a b -- c ) doc add2").unwrap();
+        assert_eq!(resources.stdout(), "a b -- c \n");
+        run(&mut vm, "doc>str add2").unwrap();
+        match &*vm.data_stack_mut().pop().unwrap() {
+            Value::StrValue(s) => assert_eq!(s, "a b -- c "),
+            v => panic!("unexpected value: {:?}", v),
+        }
+        match run(&mut vm, "doc no-such-word") {
+            Err(VmErrorReason::UndefinedWord(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "\"1 2 +\" evaluate").unwrap();
+        assert_eq!(pop_int(&mut vm), 3);
+        // definitions made inside the evaluated string persist
+        run(&mut vm, "\": sq dup * ;\" evaluate 4 sq").unwrap();
+        assert_eq!(pop_int(&mut vm), 16);
+    }
+
+    #[test]
+    fn test_include() {
+        let resources = {
+            let mut r = BufferResources::new();
+            r.add_resource(String::from("lib"), String::from(": inc 1 + ;"));
+            Rc::new(r)
+        };
+        let mut vm: TestVm = Vm::new(Rc::clone(&resources));
+        initialize(&mut vm).unwrap();
+        run(&mut vm, "\"lib\" include 5 inc").unwrap();
+        assert_eq!(pop_int(&mut vm), 6);
+    }
+
+    #[test]
+    fn test_forget() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": a 1 ; : b 2 ; forget a").unwrap();
+        assert!(vm.word_dictionary().find_word("a").is_none());
+        assert!(vm.word_dictionary().find_word("b").is_none());
+        match run(&mut vm, "a") {
+            Err(VmErrorReason::UndefinedWord(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_trap_word() {
+        let (mut vm, _) = new_test_vm();
+        match run(&mut vm, "1 trap 2") {
+            Err(VmErrorReason::TrapError(crate::lang::vm::TrapReason::UserTrap)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        vm.exec().unwrap();
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_trap_resume_after_fixing_state() {
+        let (mut vm, _) = new_test_vm();
+        match run(&mut vm, ": t trap + ; 1 t") {
+            Err(e) => assert!(e.is_resumable()),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // supply the operand the trapped code needs, then resume
+        vm.data_stack_mut().push(Rc::new(Value::IntValue(41)));
+        vm.exec().unwrap();
+        assert_eq!(pop_int(&mut vm), 42);
+        assert_eq!(vm.data_stack().here(), 0);
+        assert_eq!(vm.return_stack().here(), 0);
+        assert_eq!(vm.env_stack().here(), 0);
+    }
+
+    #[test]
+    fn test_abort_quote() {
+        let (mut vm, resources) = new_test_vm();
+        run(&mut vm, ": t abort\" boom\" ;").unwrap();
+        // a false flag is a no-op
+        run(&mut vm, "0 t").unwrap();
+        assert_eq!(resources.stderr(), "");
+        match run(&mut vm, "1 t") {
+            Err(VmErrorReason::TrapError(crate::lang::vm::TrapReason::UserTrap)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!(resources.stderr(), "boom\n");
+    }
+
+    #[test]
+    fn test_trap_code_word() {
+        let (mut vm, _) = new_test_vm();
+        match run(&mut vm, "7 trap-code") {
+            Err(VmErrorReason::TrapError(crate::lang::vm::TrapReason::UserTrapCode(7))) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_pure_words() {
+        let (vm, _) = new_test_vm();
+        assert!(vm.word_dictionary().find_word("+").unwrap().is_pure());
+        assert!(vm.word_dictionary().find_word("<").unwrap().is_pure());
+        assert!(!vm.word_dictionary().find_word(".").unwrap().is_pure());
+    }
+
+    #[test]
+    fn test_words_output() {
+        let (mut vm, resources) = new_test_vm();
+        run(&mut vm, "words").unwrap();
+        assert!(resources.stdout().contains("dup"));
+    }
+
+    #[test]
+    fn test_emit_and_print() {
+        let (mut vm, resources) = new_test_vm();
+        run(&mut vm, "\"hello\" . 65 emit cr").unwrap();
+        assert_eq!(resources.stdout(), "hello A\n");
+    }
+
+    #[test]
+    fn test_state_query() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "state? exec-state?").unwrap();
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 0);
+        // an immediate word running during compilation sees that state
+        run(&mut vm, ": probe state? ; make-immediate probe").unwrap();
+        run(&mut vm, ": w probe ;").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_script_depth_guard() {
+        let (mut vm, _) = new_test_vm();
+        assert_eq!(vm.script_depth(), 0);
+        vm.set_max_script_depth(Some(8));
+        match run(&mut vm, ": r \"r\" evaluate ; r") {
+            Err(VmErrorReason::ScriptCallDepthExceeded(max)) => assert_eq!(max, 8),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_locals_word() {
+        let (mut vm, resources) = new_test_vm();
+        run(&mut vm, ": w local x local y locals x y + ; 1 2 w").unwrap();
+        assert_eq!(pop_int(&mut vm), 3);
+        assert_eq!(resources.stdout(), "x y\n");
+        // outside a definition nothing is declared
+        run(&mut vm, "locals").unwrap();
+        assert_eq!(resources.stdout(), "x y\n");
+    }
+
+    #[test]
+    fn test_callback_resources() {
+        use crate::lang::resource::CallbackResources;
+        use std::cell::RefCell;
+        let collected = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&collected);
+        let resources = CallbackResources::new(
+            BufferResources::new(),
+            Box::new(move |text: &str| sink.borrow_mut().push(String::from(text))),
+            Box::new(|_: &str| {}),
+        );
+        let mut vm: TestVm = Vm::new(resources);
+        initialize(&mut vm).unwrap();
+        run(&mut vm, "1 2 + . 9 .").unwrap();
+        assert_eq!(
+            *collected.borrow(),
+            [String::from("3 "), String::from("9 ")]
+        );
+    }
+
+    #[test]
+    fn test_slurp_ints() {
+        let mut resources = BufferResources::new();
+        resources.add_resource(String::from("data"), String::from("1 2 foo 3"));
+        let resources = Rc::new(resources);
+        let mut vm: TestVm = Vm::new(Rc::clone(&resources));
+        initialize(&mut vm).unwrap();
+        run(&mut vm, "\"data\" slurp-ints").unwrap();
+        assert_eq!(pop_int(&mut vm), 3);
+        assert_eq!(pop_int(&mut vm), 3);
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_tokenizer_error_names_script() {
+        let mut resources = BufferResources::new();
+        resources.add_resource(String::from("good"), String::from(": one 1 ;"));
+        resources.add_resource(String::from("bad"), String::from(": two \"oops"));
+        let resources = Rc::new(resources);
+        let mut vm: TestVm = Vm::new(Rc::clone(&resources));
+        initialize(&mut vm).unwrap();
+        run(&mut vm, "\"good\" include").unwrap();
+        match run(&mut vm, "\"bad\" include") {
+            Err(e @ VmErrorReason::TokenizerError(_)) => {
+                assert!(format!("{}", e).contains("in bad at line 1"));
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_clear_stack() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "1 2 3 clear-stack depth").unwrap();
+        assert_eq!(pop_int(&mut vm), 0);
+    }
+
+    #[test]
+    fn test_scratch_stack_words() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": w >r 10 r@ + r> + ; 1 2 w").unwrap();
+        assert_eq!(pop_int(&mut vm), 14);
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(vm.scratch_stack().here(), 0);
+        // popping from an empty scratch stack underflows
+        match run(&mut vm, ": bad r> ; bad") {
+            Err(VmErrorReason::ScratchStackAccessError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        vm.reset_execution();
+        // a definition must take back what it parked
+        match run(&mut vm, ": leak 1 >r ; leak") {
+            Err(VmErrorReason::WordError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        vm.reset_execution();
+        // the words have no interpretation semantics
+        match run(&mut vm, "1 >r") {
+            Err(VmErrorReason::WordError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_auto_doc_comment() {
+        let (mut vm, _) = new_test_vm();
+        vm.set_auto_doc_comment(true);
+        run(&mut vm, "# adds two\n: add + ; doc>str add").unwrap();
+        assert_eq!(pop_str(&mut vm), "adds two");
+        // any token between the comment and the definition drops it
+        run(&mut vm, "# stray\n1 : sub - ; doc>str sub").unwrap();
+        assert_eq!(pop_str(&mut vm), "");
+        assert_eq!(pop_int(&mut vm), 1);
+        // off by default
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "# plain comment\n: mul * ; doc>str mul").unwrap();
+        assert_eq!(pop_str(&mut vm), "");
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let (mut vm, resources) = new_test_vm();
+        match run(&mut vm, ": inner backtrace trap ; : outer inner ; outer") {
+            Err(VmErrorReason::TrapError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // the chain is printed innermost caller first
+        let stdout = resources.stdout();
+        let inner = stdout.find("inner").unwrap();
+        let outer = stdout.find("outer").unwrap();
+        assert!(inner < outer);
+    }
+
+    #[test]
+    fn test_marker() {
+        let (mut vm, _) = new_test_vm();
+        run(
+            &mut vm,
+            "marker cleanup : tmp1 1 ; : tmp2 2 ; create buf cleanup",
+        )
+        .unwrap();
+        run(
+            &mut vm,
+            "defined? tmp1 defined? tmp2 defined? buf defined? cleanup",
+        )
+        .unwrap();
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 0);
+        assert_eq!(pop_int(&mut vm), 0);
+        // the code buffer shrinks back to where the marker was defined
+        let before = vm.code_buffer().here();
+        run(&mut vm, "marker m : tmp3 3 ; m").unwrap();
+        assert_eq!(vm.code_buffer().here(), before);
+    }
+
+    #[test]
+    fn test_time_word() {
+        let (mut vm, _) = new_test_vm();
+        // the quotation may leave values on the stack; the start
+        // timestamp is parked on the scratch stack so they survive
+        run(&mut vm, ":noname 1000 0 do loop 7 ; time").unwrap();
+        assert!(pop_int(&mut vm) >= 0);
+        assert_eq!(pop_int(&mut vm), 7);
+        assert_eq!(vm.data_stack().here(), 0);
+    }
+
+    #[test]
+    fn test_char_str_conversion() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "65 char>str \"abc\" str>char").unwrap();
+        assert_eq!(pop_int(&mut vm), 97);
+        assert_eq!(pop_str(&mut vm), "A");
+        // a surrogate code point is not a char
+        match run(&mut vm, "55296 char>str") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match run(&mut vm, "\"\" str>char") {
+            Err(VmErrorReason::TypeMismatchError(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, "parse-line  rest of the line\n1").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+        assert_eq!(pop_str(&mut vm), " rest of the line");
+        // a CRLF line ending never leaks its CR into the text
+        run(&mut vm, "parse-line x y\r\n2").unwrap();
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_str(&mut vm), "x y");
+    }
+
+    #[test]
+    fn test_see_primitive_word() {
+        let (mut vm, resources) = new_test_vm();
+        run(&mut vm, "see +").unwrap();
+        let out = resources.stdout();
+        assert!(out.contains("(primitive)"), "unexpected output: {}", out);
+        assert!(!out.contains("CallPrimitive"), "unexpected output: {}", out);
+    }
+
+    #[test]
+    fn test_where_reports_source() {
+        let mut resources = BufferResources::new();
+        resources.add_resource(String::from("lib"), String::from(": w 1 2 + ;"));
+        let resources = Rc::new(resources);
+        let mut vm: TestVm = Vm::new(Rc::clone(&resources));
+        initialize(&mut vm).unwrap();
+        run(&mut vm, "\"lib\" include").unwrap();
+        run(&mut vm, "where w").unwrap();
+        for line in resources.stdout().lines() {
+            assert!(line.contains("lib:1:"), "unexpected line: {}", line);
+        }
+        match run(&mut vm, "where missing") {
+            Err(VmErrorReason::UndefinedWord(name)) => assert_eq!(name, "missing"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        use crate::lang::resource::ResourceErrorReason;
+
+        let mut resources = BufferResources::new();
+        resources.add_resource(String::from("a"), String::from("\"b\" include"));
+        resources.add_resource(String::from("b"), String::from("\"a\" include"));
+        let mut vm: TestVm = Vm::new(Rc::new(resources));
+        initialize(&mut vm).unwrap();
+        match run(&mut vm, "\"a\" include") {
+            Err(VmErrorReason::ResourceError(ResourceErrorReason::IncludeCycle(name))) => {
+                assert_eq!(name, "a")
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_include_recovers_after_depth_error() {
+        let mut resources = BufferResources::new();
+        resources.add_resource(String::from("lib"), String::from("1"));
+        let mut vm: TestVm = Vm::new(Rc::new(resources));
+        initialize(&mut vm).unwrap();
+        vm.set_max_script_depth(Some(1));
+        match run(&mut vm, "\"lib\" include") {
+            Err(VmErrorReason::ScriptCallDepthExceeded(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // the failed include left no stale chain entry behind, so
+        // including the module again is not a cycle
+        vm.reset_execution();
+        vm.set_max_script_depth(None);
+        run(&mut vm, "\"lib\" include").unwrap();
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_word_instructions() {
+        use crate::lang::vm::Instruction;
+
+        let (mut vm, _) = new_test_vm();
+        run(&mut vm, ": sq dup * ;").unwrap();
+        let code = vm.word_dictionary().find_word("sq").unwrap().code();
+        let body: Vec<_> = vm.code_buffer().word_instructions(code).collect();
+        // dup call, * call and the return; the terminator is not
+        // yielded
+        assert_eq!(body.len(), 3);
+        assert_eq!(body[0].0, code);
+        assert!(matches!(body[0].1, Instruction::Call(_)));
+        assert!(matches!(body[1].1, Instruction::Call(_)));
+        assert!(matches!(body[2].1, Instruction::Return));
+    }
+
+    #[test]
+    fn test_unknown_symbol_handler() {
+        use crate::lang::vm::Instruction;
+
+        let (mut vm, _) = new_test_vm();
+        // without a handler the unknown name is an error
+        match run(&mut vm, ": w1 $foo ;") {
+            Err(VmErrorReason::UndefinedWord(name)) => assert_eq!(name, "$foo"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        let (mut vm, _) = new_test_vm();
+        vm.set_unknown_symbol_handler(Box::new(|name| {
+            if name.starts_with('$') {
+                Some(Instruction::Push(Rc::new(Value::IntValue(42))))
+            } else {
+                None
+            }
+        }));
+        run(&mut vm, ": w2 $foo 1 + ; w2").unwrap();
+        assert_eq!(pop_int(&mut vm), 43);
+        // names without the prefix still error
+        match run(&mut vm, ": w3 bar ;") {
+            Err(VmErrorReason::UndefinedWord(name)) => assert_eq!(name, "bar"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_failing_stdout_propagates() {
+        use crate::lang::resource::ResourceErrorReason;
+        use crate::lang::resource::Resources;
+        use crate::lang::tokenizer::TokenIterator;
+
+        struct FailingResources;
+        impl Resources for FailingResources {
+            fn get_string(&self, name: &str) -> Result<String, ResourceErrorReason> {
+                Err(ResourceErrorReason::ResourceNotFound(String::from(name)))
+            }
+            fn get_bytes(&self, name: &str) -> Result<Vec<u8>, ResourceErrorReason> {
+                Err(ResourceErrorReason::ResourceNotFound(String::from(name)))
+            }
+            fn get_token_iterator(
+                &self,
+                name: &str,
+            ) -> Result<Box<dyn TokenIterator>, ResourceErrorReason> {
+                Err(ResourceErrorReason::ResourceNotFound(String::from(name)))
+            }
+            fn write_stdout(&self, _: &str) -> Result<(), ResourceErrorReason> {
+                Err(ResourceErrorReason::IOError(String::from("broken pipe")))
+            }
+            fn write_stderr(&self, _: &str) -> Result<(), ResourceErrorReason> {
+                Err(ResourceErrorReason::IOError(String::from("broken pipe")))
+            }
+        }
+
+        let mut vm: TestVm = Vm::new(Rc::new(FailingResources));
+        initialize(&mut vm).unwrap();
+        match run(&mut vm, "1 .") {
+            Err(VmErrorReason::ResourceError(ResourceErrorReason::IOError(_))) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
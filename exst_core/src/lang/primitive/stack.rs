@@ -0,0 +1,209 @@
+//! stack manipulation words
+
+use super::util;
+use crate::lang::vm::value::Value;
+use crate::lang::vm::Instruction;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use crate::lang::VmInt;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>) {
+    vm.define_primitive_word("dup", false, "a -- a a", dup);
+    vm.define_primitive_word("drop", false, "a --", drop_word);
+    vm.define_primitive_word("swap", false, "a b -- b a", swap);
+    vm.define_primitive_word("over", false, "a b -- a b a", over);
+    vm.define_primitive_word("rot", false, "a b c -- b c a", rot);
+    vm.define_primitive_word("pick", false, "xu .. x0 u -- xu .. x0 xu", pick);
+    vm.define_primitive_word("roll", false, "xu .. x0 u -- xu-1 .. x0 xu", roll);
+    vm.define_primitive_word("depth", false, "-- n : depth of the data stack", depth);
+    vm.define_primitive_word(
+        "clear-stack",
+        false,
+        "... -- : remove every value from the data stack",
+        clear_stack,
+    );
+    vm.define_primitive_word(
+        ">r",
+        true,
+        "x -- : move the top to the scratch stack; compile only",
+        to_r,
+    );
+    vm.define_primitive_word(
+        "r>",
+        true,
+        "-- x : move the scratch stack top back; compile only",
+        r_from,
+    );
+    vm.define_primitive_word(
+        "r@",
+        true,
+        "-- x : copy the scratch stack top; compile only",
+        r_fetch,
+    );
+    vm.define_primitive_word("sdup", false, "s -- s s' : duplicate a string deeply", sdup);
+    vm.define_primitive_word(
+        "clone-value",
+        false,
+        "x -- x' : replace the top with an unshared copy",
+        clone_value,
+    );
+    vm.define_primitive_word(
+        "stack-save",
+        false,
+        "-- handle : snapshot the data stack",
+        stack_save,
+    );
+    vm.define_primitive_word(
+        "stack-restore",
+        false,
+        "handle -- : restore the data stack from a snapshot",
+        stack_restore,
+    );
+    vm.define_primitive_word("arg", false, "n -- x : n-th program argument", arg);
+    vm.define_primitive_word("argc", false, "-- n : number of program arguments", argc);
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    "
+    : nip swap drop ;
+    : tuck swap over ;
+    : 2dup over over ;
+    : 2drop drop drop ;
+    "
+}
+
+fn dup<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = vm.data_stack().pick(0)?;
+    vm.data_stack_mut().push(v);
+    Ok(())
+}
+
+fn drop_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::pop(vm)?;
+    Ok(())
+}
+
+fn swap<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    Ok(vm.data_stack_mut().roll(1)?)
+}
+
+fn over<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = vm.data_stack().pick(1)?;
+    vm.data_stack_mut().push(v);
+    Ok(())
+}
+
+fn rot<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    Ok(vm.data_stack_mut().roll(2)?)
+}
+
+fn pick<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let u = util::pop_int(vm)?;
+    let u = usize::try_from(u).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let v = vm.data_stack().pick(u)?;
+    vm.data_stack_mut().push(v);
+    Ok(())
+}
+
+fn roll<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let u = util::pop_int(vm)?;
+    let u = usize::try_from(u).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    Ok(vm.data_stack_mut().roll(u)?)
+}
+
+fn sdup<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = vm.data_stack().pick(0)?;
+    match v.as_ref() {
+        Value::StrValue(s) => {
+            util::push_value(vm, Value::StrValue(s.clone()));
+            Ok(())
+        }
+        _ => Err(VmErrorReason::TypeMismatchError("str")),
+    }
+}
+
+fn clone_value<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = util::pop(vm)?;
+    // only strings carry a heap buffer worth copying; every other
+    // variant is either `Copy` or deliberately left shared
+    let copy = match v.as_ref() {
+        Value::StrValue(s) => Rc::new(Value::StrValue(s.clone())),
+        Value::IntValue(i) => Rc::new(Value::IntValue(*i)),
+        Value::FloatValue(f) => Rc::new(Value::FloatValue(*f)),
+        Value::CodeAddress(a) => Rc::new(Value::CodeAddress(*a)),
+        Value::DataAddress(a) => Rc::new(Value::DataAddress(*a)),
+        Value::ExtValue(_) => v,
+    };
+    vm.data_stack_mut().push(copy);
+    Ok(())
+}
+
+fn stack_save<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let handle = vm.take_stack_snapshot();
+    let handle = VmInt::try_from(handle)
+        .map_err(|_| VmErrorReason::TypeMismatchError("int range handle"))?;
+    util::push_int(vm, handle);
+    Ok(())
+}
+
+fn stack_restore<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let handle = util::pop_int(vm)?;
+    let handle =
+        usize::try_from(handle).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    if vm.restore_stack_snapshot(handle) {
+        Ok(())
+    } else {
+        Err(VmErrorReason::TypeMismatchError("live snapshot handle"))
+    }
+}
+
+fn clear_stack<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    vm.data_stack_mut().clear();
+    Ok(())
+}
+
+fn to_r<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    vm.compile_instruction(Instruction::ScratchPush);
+    Ok(())
+}
+
+fn r_from<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    vm.compile_instruction(Instruction::ScratchPop);
+    Ok(())
+}
+
+fn r_fetch<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    vm.compile_instruction(Instruction::ScratchCopy);
+    Ok(())
+}
+
+fn depth<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let n = vm.data_stack().here();
+    util::push_int(vm, n as VmInt);
+    Ok(())
+}
+
+fn arg<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let n = util::pop_int(vm)?;
+    let n = usize::try_from(n).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let v = Rc::clone(
+        vm.env_stack()
+            .get(n)
+            .map_err(VmErrorReason::EnvStackAccessError)?,
+    );
+    vm.data_stack_mut().push(v);
+    Ok(())
+}
+
+fn argc<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let n = vm.env_stack().here();
+    util::push_int(vm, n as VmInt);
+    Ok(())
+}
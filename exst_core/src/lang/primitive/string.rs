@@ -0,0 +1,90 @@
+//! string processing words
+
+use super::util;
+use crate::lang::vm::value::Value;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use crate::lang::VmInt;
+use std::convert::TryFrom;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>) {
+    vm.define_primitive_word(
+        "split",
+        false,
+        "s delim -- s1 .. sn n : split a string on a character",
+        split,
+    );
+    vm.define_primitive_word(
+        "char>str",
+        false,
+        "n -- s : one-character string from a code point",
+        char_to_str,
+    );
+    vm.define_primitive_word(
+        "str>char",
+        false,
+        "s -- n : code point of the first character",
+        str_to_char,
+    );
+    vm.define_primitive_word(
+        "join",
+        false,
+        "s1 .. sn n sep -- s : join n strings with a separator",
+        join,
+    );
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    ""
+}
+
+fn split<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let delim = match *util::pop(vm)? {
+        Value::IntValue(i) => u32::try_from(i)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or(VmErrorReason::TypeMismatchError("char"))?,
+        _ => return Err(VmErrorReason::TypeMismatchError("char")),
+    };
+    let body = util::pop_str(vm)?;
+    let mut count: VmInt = 0;
+    for piece in body.split(delim) {
+        util::push_value(vm, Value::StrValue(String::from(piece)));
+        count += 1;
+    }
+    util::push_int(vm, count);
+    Ok(())
+}
+
+fn char_to_str<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let c = util::pop(vm)?
+        .try_into_char()
+        .ok_or(VmErrorReason::TypeMismatchError("char"))?;
+    util::push_value(vm, Value::StrValue(String::from(c)));
+    Ok(())
+}
+
+fn str_to_char<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let body = util::pop_str(vm)?;
+    let c = body
+        .chars()
+        .next()
+        .ok_or(VmErrorReason::TypeMismatchError("non-empty string"))?;
+    util::push_int(vm, c as u32 as VmInt);
+    Ok(())
+}
+
+fn join<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let sep = util::pop_str(vm)?;
+    let n = util::pop_int(vm)?;
+    let n = usize::try_from(n).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let mut pieces = Vec::with_capacity(n);
+    for _ in 0..n {
+        pieces.push(util::pop_str(vm)?);
+    }
+    pieces.reverse();
+    util::push_value(vm, Value::StrValue(pieces.join(&sep)));
+    Ok(())
+}
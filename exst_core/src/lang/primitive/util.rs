@@ -0,0 +1,99 @@
+//! shared helpers for primitive word implementations
+
+use crate::lang::vm::buffer::CodeAddress;
+use crate::lang::vm::buffer::DataAddress;
+use crate::lang::vm::value::Value;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use crate::lang::vm::VmState;
+use crate::lang::VmInt;
+use std::rc::Rc;
+
+/// pop the top of the data stack
+pub fn pop<T, E>(vm: &mut Vm<T, E>) -> Result<Rc<Value<T>>, VmErrorReason<E>> {
+    Ok(vm.data_stack_mut().pop()?)
+}
+
+/// pop the top of the data stack as an integer
+pub fn pop_int<T, E>(vm: &mut Vm<T, E>) -> Result<VmInt, VmErrorReason<E>> {
+    match *pop(vm)? {
+        Value::IntValue(i) => Ok(i),
+        _ => Err(VmErrorReason::TypeMismatchError("int")),
+    }
+}
+
+/// pop the top of the data stack as a string
+pub fn pop_str<T, E>(vm: &mut Vm<T, E>) -> Result<String, VmErrorReason<E>> {
+    match pop(vm)?.as_ref() {
+        Value::StrValue(s) => Ok(s.clone()),
+        _ => Err(VmErrorReason::TypeMismatchError("str")),
+    }
+}
+
+/// pop the top of the data stack as a code address
+pub fn pop_code_address<T, E>(vm: &mut Vm<T, E>) -> Result<CodeAddress, VmErrorReason<E>> {
+    match *pop(vm)? {
+        Value::CodeAddress(a) => Ok(a),
+        _ => Err(VmErrorReason::TypeMismatchError("code address")),
+    }
+}
+
+/// pop the top of the data stack as a data address
+pub fn pop_data_address<T, E>(vm: &mut Vm<T, E>) -> Result<DataAddress, VmErrorReason<E>> {
+    match *pop(vm)? {
+        Value::DataAddress(a) => Ok(a),
+        _ => Err(VmErrorReason::TypeMismatchError("data address")),
+    }
+}
+
+/// push a value on the data stack
+pub fn push_value<T, E>(vm: &mut Vm<T, E>, v: Value<T>) {
+    vm.data_stack_mut().push(Rc::new(v));
+}
+
+/// push an integer on the data stack
+pub fn push_int<T, E>(vm: &mut Vm<T, E>, i: VmInt) {
+    push_value(vm, Value::IntValue(i));
+}
+
+/// push a flag on the data stack, as the integer one or zero
+pub fn push_bool<T, E>(vm: &mut Vm<T, E>, b: bool) {
+    push_int(vm, if b { 1 } else { 0 });
+}
+
+/// error unless the VM is compiling a definition
+pub fn require_compiling<T, E>(vm: &Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    match vm.state() {
+        VmState::Compilation | VmState::RecursableCompilation => Ok(()),
+        VmState::Interpretation => Err(VmErrorReason::WordError("compile only word")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::resource::BufferResources;
+
+    type TestVm = Vm<i32, i32>;
+
+    #[test]
+    fn test_pop_typed() {
+        let mut vm: TestVm = Vm::new(BufferResources::new());
+        push_int(&mut vm, 3);
+        assert_eq!(pop_int(&mut vm).unwrap(), 3);
+        push_value(&mut vm, Value::StrValue(String::from("a")));
+        match pop_int(&mut vm) {
+            Err(VmErrorReason::TypeMismatchError("int")) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_push_bool() {
+        let mut vm: TestVm = Vm::new(BufferResources::new());
+        push_bool(&mut vm, true);
+        push_bool(&mut vm, false);
+        assert_eq!(pop_int(&mut vm).unwrap(), 0);
+        assert_eq!(pop_int(&mut vm).unwrap(), 1);
+    }
+}
@@ -0,0 +1,589 @@
+//! word definition and compilation words
+
+use super::util;
+use crate::lang::tokenizer::new_token_stream_from_string;
+use crate::lang::vm::buffer::CodeAddress;
+use crate::lang::vm::value::Value;
+use crate::lang::vm::Instruction;
+use crate::lang::vm::RedefinitionCheckMode;
+use crate::lang::vm::StackCheckMode;
+use crate::lang::vm::TrapReason;
+use crate::lang::vm::Vm;
+use crate::lang::vm::VmErrorReason;
+use crate::lang::vm::VmExecutionState;
+use crate::lang::vm::VmState;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// register the words of this module
+pub fn initialize<T, E>(vm: &mut Vm<T, E>) {
+    vm.define_primitive_word(":", false, "\"name\" -- : start a word definition", colon);
+    vm.define_primitive_word(";", true, "-- : end a word definition", semicolon);
+    vm.define_primitive_word(
+        ":noname",
+        false,
+        "-- : start an anonymous word definition",
+        noname,
+    );
+    vm.define_primitive_word(
+        "immidiate",
+        false,
+        "-- : make the last word immediate",
+        immidiate,
+    );
+    vm.define_primitive_word(
+        "make-immediate",
+        false,
+        "\"name\" -- : make a named word immediate",
+        make_immediate,
+    );
+    vm.define_primitive_word(
+        "make-normal",
+        false,
+        "\"name\" -- : make a named word compile normally again",
+        make_normal,
+    );
+    vm.define_primitive_word(
+        "immediate?",
+        false,
+        "\"name\" -- flag : true when the named word is immediate",
+        immediate_query,
+    );
+    vm.define_primitive_word(
+        "recursable",
+        true,
+        "-- : allow the current definition to call itself",
+        recursable,
+    );
+    vm.define_primitive_word("'", false, "\"name\" -- xt : execution token of a word", tick);
+    vm.define_primitive_word(
+        "latest",
+        false,
+        "-- xt : execution token of the last completed word",
+        latest,
+    );
+    vm.define_primitive_word(
+        "literal",
+        true,
+        "x -- : compile a push of the given value",
+        literal,
+    );
+    vm.define_primitive_word(
+        "compile,",
+        true,
+        "xt -- : compile a call to the given word",
+        compile_comma,
+    );
+    vm.define_primitive_word(
+        "long-jump,",
+        true,
+        "xt n -- : compile a long jump that keeps the top n values",
+        long_jump_comma,
+    );
+    vm.define_primitive_word("[", true, "-- : switch to interpretation", left_bracket);
+    vm.define_primitive_word("]", false, "-- : switch back to compilation", right_bracket);
+    vm.define_primitive_word(
+        "parse",
+        false,
+        "c -- str : read raw input up to the character c",
+        parse,
+    );
+    vm.define_primitive_word(
+        "parse-line",
+        false,
+        "-- str : read the rest of the current line",
+        parse_line,
+    );
+    vm.define_primitive_word(
+        "document-word",
+        false,
+        "str -- : set the documentation of the last word",
+        document_word,
+    );
+    vm.define_primitive_word(
+        "doc",
+        false,
+        "\"name\" -- : print the documentation of a word",
+        doc,
+    );
+    vm.define_primitive_word(
+        "doc>str",
+        false,
+        "\"name\" -- str : documentation of a word",
+        doc_str,
+    );
+    vm.define_primitive_word("include", false, "name -- : run the named resource", include);
+    vm.define_primitive_word(
+        "evaluate",
+        false,
+        "s -- : run a string as source in the current state",
+        evaluate,
+    );
+    vm.define_primitive_word(
+        "vocabulary",
+        false,
+        "\"name\" -- : create a vocabulary",
+        vocabulary,
+    );
+    vm.define_primitive_word(
+        "also",
+        false,
+        "\"name\" -- : push a vocabulary onto the search order",
+        also,
+    );
+    vm.define_primitive_word(
+        "previous",
+        false,
+        "-- : pop the top of the search order",
+        previous,
+    );
+    vm.define_primitive_word("forget", false, "\"name\" -- : forget a word and everything after it", forget);
+    vm.define_primitive_word(
+        "marker",
+        false,
+        "\"name\" -- : define a word that forgets itself and everything after it",
+        marker,
+    );
+    vm.define_primitive_word("alias", false, "\"new\" \"old\" -- : define an alias", alias);
+    vm.define_primitive_word("defined?", false, "\"name\" -- flag", defined);
+    vm.define_primitive_word(
+        "local",
+        true,
+        "x -- : pop the top of the stack into a new local",
+        local,
+    );
+    vm.define_primitive_word("cdp", false, "-- addr : current code buffer top", cdp);
+    vm.define_primitive_word("bye", false, "-- : stop the machine", bye);
+    vm.define_primitive_word("exit", true, "-- : compile a return from the current word", exit);
+    vm.define_word_with_instructions("exec", false, "xt -- : call an execution token", vec![Instruction::Exec]);
+    vm.define_word_with_instructions(
+        "trap",
+        false,
+        "-- : abort execution",
+        vec![Instruction::Trap(TrapReason::UserTrap)],
+    );
+    vm.define_primitive_word(
+        "abort\"",
+        true,
+        "flag -- : when the flag is true, print a message and trap",
+        abort_quote,
+    );
+    vm.define_primitive_word(
+        "trap-code",
+        false,
+        "n -- : abort execution with the given code",
+        trap_code,
+    );
+}
+
+/// script preloaded after every module is registered
+pub fn preload_script() -> &'static str {
+    "
+    : ( 41 parse document-word ; immidiate
+    "
+}
+
+fn colon<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    match vm.redefinition_check_mode() {
+        RedefinitionCheckMode::Off => {}
+        mode => {
+            if vm.word_dictionary().find_word(&name).is_some() {
+                if mode == RedefinitionCheckMode::Error {
+                    return Err(VmErrorReason::WordError("redefinition of an existing word"));
+                }
+                vm.resources()
+                    .write_stderr(&format!("redefinition of {}\n", name))?;
+            }
+        }
+    }
+    vm.begin_word_def(name, false)
+}
+
+/// simulate the net stack depth of the reserved definition
+///
+/// Only straight-line bodies of words with declared effects can be
+/// verified; anything else returns None (nothing to report).
+fn check_stack_effect<T, E>(vm: &Vm<T, E>) -> Option<&'static str> {
+    let (_, word) = vm.word_dictionary().reserved_word_def()?;
+    let effect = word.stack_effect()?;
+    let start = usize::try_from(word.code()).ok()?;
+    let end = vm.code_buffer().len();
+    let mut depth = effect.inputs as i64;
+    for i in start..end {
+        match vm.code_buffer().get(CodeAddress::from_index(i)).ok()? {
+            Instruction::Push(_) | Instruction::LocalRef(_) => depth += 1,
+            Instruction::LocalDef => depth -= 1,
+            Instruction::Call(a) => {
+                let name = vm.word_dictionary().find_name(*a)?;
+                let called = vm.word_dictionary().find_word(name)?.stack_effect()?;
+                depth -= called.inputs as i64;
+                if depth < 0 {
+                    return Some("definition pops more than its declared inputs");
+                }
+                depth += called.outputs as i64;
+            }
+            // branches and the like make the depth data dependent
+            _ => return None,
+        }
+        if depth < 0 {
+            return Some("definition pops more than its declared inputs");
+        }
+    }
+    if depth != effect.outputs as i64 {
+        return Some("definition does not leave its declared outputs");
+    }
+    None
+}
+
+fn semicolon<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    // an unterminated if/do would otherwise complete with dangling
+    // branch targets
+    if vm.controlflow_stack().here() != 0 {
+        return Err(VmErrorReason::WordError(
+            "unbalanced control structure in definition",
+        ));
+    }
+    match vm.stack_check_mode() {
+        StackCheckMode::Off => {}
+        mode => {
+            if let Some(message) = check_stack_effect(vm) {
+                if mode == StackCheckMode::Error {
+                    return Err(VmErrorReason::WordError(message));
+                }
+                vm.resources().write_stderr(&format!("stack check: {}\n", message))?;
+            }
+        }
+    }
+    vm.end_word_def()
+}
+
+fn noname<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    vm.begin_word_def(String::new(), true)
+}
+
+fn immidiate<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    match vm.word_dictionary_mut().last_word_mut() {
+        Some(word) => {
+            word.set_immediate();
+            Ok(())
+        }
+        None => Err(VmErrorReason::WordError("no word defined")),
+    }
+}
+
+fn make_immediate<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    match vm.word_dictionary_mut().find_word_mut(&name) {
+        Some(word) => {
+            word.set_immediate();
+            Ok(())
+        }
+        None => Err(VmErrorReason::UndefinedWord(name)),
+    }
+}
+
+fn make_normal<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    match vm.word_dictionary_mut().find_word_mut(&name) {
+        Some(word) => {
+            word.clear_immediate();
+            Ok(())
+        }
+        None => Err(VmErrorReason::UndefinedWord(name)),
+    }
+}
+
+fn immediate_query<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    match vm.word_dictionary().find_word(&name) {
+        Some(word) => {
+            let immediate = word.immediate();
+            util::push_bool(vm, immediate);
+            Ok(())
+        }
+        None => Err(VmErrorReason::UndefinedWord(name)),
+    }
+}
+
+fn recursable<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    vm.set_state(VmState::RecursableCompilation);
+    Ok(())
+}
+
+fn tick<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    match vm.word_dictionary().find_word(&name) {
+        Some(word) => {
+            let code = word.code();
+            util::push_value(vm, Value::CodeAddress(code));
+            Ok(())
+        }
+        None => Err(VmErrorReason::UndefinedWord(name)),
+    }
+}
+
+fn latest<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    match vm.word_dictionary().last_completed_word() {
+        Some(word) => {
+            let code = word.code();
+            util::push_value(vm, Value::CodeAddress(code));
+            Ok(())
+        }
+        None => Err(VmErrorReason::WordError("no word defined")),
+    }
+}
+
+fn literal<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let v = util::pop(vm)?;
+    vm.compile_instruction(Instruction::Push(v));
+    Ok(())
+}
+
+fn compile_comma<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let code = util::pop_code_address(vm)?;
+    vm.compile_instruction(Instruction::Call(code));
+    Ok(())
+}
+
+fn long_jump_comma<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let n = util::pop_int(vm)?;
+    let n = usize::try_from(n).map_err(|_| VmErrorReason::TypeMismatchError("non-negative int"))?;
+    let code = util::pop_code_address(vm)?;
+    vm.compile_instruction(Instruction::LongJumpN(code, n));
+    Ok(())
+}
+
+fn left_bracket<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    vm.set_state(VmState::Interpretation);
+    Ok(())
+}
+
+fn right_bracket<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    if vm.word_dictionary().reserved_word_def().is_none() {
+        return Err(VmErrorReason::WordError("no word definition to continue"));
+    }
+    vm.set_state(VmState::Compilation);
+    Ok(())
+}
+
+fn parse<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let v = util::pop(vm)?;
+    let c = v
+        .try_into_char()
+        .ok_or(VmErrorReason::TypeMismatchError("char"))?;
+    let body = vm.input_stream_mut().skip(c)?;
+    util::push_value(vm, Value::StrValue(body));
+    Ok(())
+}
+
+fn parse_line<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let mut body = vm.input_stream_mut().skip('\n')?;
+    // a CRLF input leaves the CR on the captured text
+    if body.ends_with('\r') {
+        body.pop();
+    }
+    util::push_value(vm, Value::StrValue(body));
+    Ok(())
+}
+
+fn document_word<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let document = util::pop_str(vm)?;
+    match vm.word_dictionary_mut().last_word_mut() {
+        Some(word) => {
+            word.set_document(document);
+            Ok(())
+        }
+        None => Err(VmErrorReason::WordError("no word defined")),
+    }
+}
+
+/// documentation of the named word
+fn find_document<T, E>(vm: &mut Vm<T, E>) -> Result<String, VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    match vm.word_dictionary().find_word(&name) {
+        Some(word) => Ok(String::from(word.document())),
+        None => Err(VmErrorReason::UndefinedWord(name)),
+    }
+}
+
+fn doc<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let document = find_document(vm)?;
+    vm.resources().write_stdout(&document)?;
+    vm.resources().write_stdout("\n")?;
+    Ok(())
+}
+
+fn doc_str<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let document = find_document(vm)?;
+    util::push_value(vm, Value::StrValue(document));
+    Ok(())
+}
+
+fn include<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = util::pop_str(vm)?;
+    let stream = vm.resources().get_token_iterator(&name)?;
+    vm.begin_include(&name)?;
+    let result = vm.try_call_script(stream);
+    if result.is_err() {
+        vm.cancel_include(&name);
+    }
+    result
+}
+
+fn evaluate<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let body = util::pop_str(vm)?;
+    vm.try_call_script(Box::new(new_token_stream_from_string(
+        body,
+        String::from("<evaluate>"),
+    )))
+}
+
+fn vocabulary<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    vm.word_dictionary_mut().create_vocabulary(name);
+    Ok(())
+}
+
+fn also<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    if vm.word_dictionary_mut().push_search_order(&name) {
+        Ok(())
+    } else {
+        Err(VmErrorReason::WordError("unknown vocabulary"))
+    }
+}
+
+fn previous<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    if vm.word_dictionary_mut().pop_search_order() {
+        Ok(())
+    } else {
+        Err(VmErrorReason::WordError("search order is empty"))
+    }
+}
+
+fn forget<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    let code = match vm.word_dictionary().find_word(&name) {
+        Some(word) => word.code(),
+        None => return Err(VmErrorReason::UndefinedWord(name)),
+    };
+    let len = usize::try_from(code)?;
+    vm.code_buffer_mut()
+        .rollback(len)
+        .map_err(VmErrorReason::CodeBufferAccessError)?;
+    vm.word_dictionary_mut().forget(code);
+    vm.debug_info_store_mut().remove_from(code);
+    Ok(())
+}
+
+fn marker<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    let code = vm.code_buffer().here();
+    let data = vm.data_buffer().here();
+    vm.define_word_with_instructions(
+        &name,
+        false,
+        "-- : forget this word and everything defined after it",
+        vec![
+            Instruction::Push(Rc::new(Value::CodeAddress(code))),
+            Instruction::Push(Rc::new(Value::DataAddress(data))),
+            Instruction::CallPrimitive(marker_runtime),
+        ],
+    );
+    Ok(())
+}
+
+fn marker_runtime<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let data = util::pop(vm)?;
+    let code = util::pop(vm)?;
+    let (code, data) = match (&*code, &*data) {
+        (Value::CodeAddress(c), Value::DataAddress(d)) => (*c, *d),
+        _ => return Err(VmErrorReason::TypeMismatchError("marker addresses")),
+    };
+    // the marker word's own body is part of what gets truncated, so
+    // unwind the frame before the remaining instructions disappear
+    vm.return_from_current_word()?;
+    vm.code_buffer_mut()
+        .rollback(usize::try_from(code)?)
+        .map_err(VmErrorReason::CodeBufferAccessError)?;
+    vm.rollback_data_buffer(usize::try_from(data)?)?;
+    vm.word_dictionary_mut().forget(code);
+    vm.debug_info_store_mut().remove_from(code);
+    Ok(())
+}
+
+fn alias<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let new_name = vm.next_symbol_token()?;
+    let old_name = vm.next_symbol_token()?;
+    match vm.word_dictionary().find_word(&old_name) {
+        Some(word) => {
+            let word = word.clone();
+            vm.word_dictionary_mut().define(new_name, word);
+            Ok(())
+        }
+        None => Err(VmErrorReason::UndefinedWord(old_name)),
+    }
+}
+
+fn defined<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let name = vm.next_symbol_token()?;
+    let found = vm.word_dictionary().find_word(&name).is_some();
+    util::push_bool(vm, found);
+    Ok(())
+}
+
+fn local<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let name = vm.next_symbol_token()?;
+    vm.local_dictionary_mut().define(name);
+    vm.compile_instruction(Instruction::LocalDef);
+    Ok(())
+}
+
+fn cdp<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let code = vm.code_buffer().here();
+    util::push_value(vm, Value::CodeAddress(code));
+    Ok(())
+}
+
+fn abort_quote<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    let message = vm.input_stream_mut().skip('"')?;
+    vm.compile_instruction(Instruction::Push(Rc::new(Value::StrValue(message))));
+    vm.compile_instruction(Instruction::CallPrimitive(abort_runtime));
+    Ok(())
+}
+
+fn abort_runtime<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let message = util::pop_str(vm)?;
+    let flag = util::pop(vm)?;
+    if flag.is_true() {
+        vm.resources().write_stderr(&format!("{}\n", message))?;
+        return Err(VmErrorReason::TrapError(TrapReason::UserTrap));
+    }
+    Ok(())
+}
+
+fn trap_code<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    let n = util::pop_int(vm)?;
+    let code = i32::try_from(i128::from(n))
+        .map_err(|_| VmErrorReason::TypeMismatchError("trap code in i32 range"))?;
+    Err(VmErrorReason::TrapError(TrapReason::UserTrapCode(code)))
+}
+
+fn bye<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    vm.set_exec_state(VmExecutionState::FinishState);
+    Ok(())
+}
+
+fn exit<T, E>(vm: &mut Vm<T, E>) -> Result<(), VmErrorReason<E>> {
+    util::require_compiling(vm)?;
+    vm.compile_instruction(Instruction::Return);
+    Ok(())
+}
@@ -0,0 +1,560 @@
+//! resource access
+//!
+//! Scripts refer to their inputs by resource name. The name decides
+//! where the body comes from:
+//!
+//! * `&NAME` - the environment variable `NAME`
+//! * `:path` - the file `path` below the project root
+//! * `%STDIN` - the standard input
+//! * anything else - a named resource registered on the implementation
+//!
+//! The `Resources` trait also routes the output of the VM so that
+//! front ends can redirect it.
+
+use super::tokenizer::create_token_iterator;
+use super::tokenizer::new_token_stream_from_string;
+use super::tokenizer::TokenIterator;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// reason of a resource error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceErrorReason {
+    /// no resource exists under the given name
+    ResourceNotFound(String),
+    /// the environment variable behind a `&NAME` resource is not set
+    EnvVarNotSet(String),
+    /// a `:path` resource resolves outside the project root
+    PathEscape(String),
+    /// the resource body is not valid UTF-8
+    InvalidUtf8(String),
+    /// an I/O error occurred while loading the resource
+    IOError(String),
+    /// a module includes itself, directly or indirectly
+    IncludeCycle(String),
+}
+
+/// resource loading and output routing
+pub trait Resources {
+    /// load the entire resource body as a string
+    fn get_string(&self, name: &str) -> Result<String, ResourceErrorReason>;
+    /// load the entire resource body as raw bytes
+    fn get_bytes(&self, name: &str) -> Result<Vec<u8>, ResourceErrorReason>;
+    /// create a token iterator over the resource body
+    fn get_token_iterator(&self, name: &str)
+        -> Result<Box<dyn TokenIterator>, ResourceErrorReason>;
+    /// write text to the standard output
+    fn write_stdout(&self, text: &str) -> Result<(), ResourceErrorReason>;
+    /// write text to the standard error
+    fn write_stderr(&self, text: &str) -> Result<(), ResourceErrorReason>;
+}
+
+/// sharing a resource implementation behind an `Rc` is transparent
+impl<R: Resources> Resources for std::rc::Rc<R> {
+    fn get_string(&self, name: &str) -> Result<String, ResourceErrorReason> {
+        self.as_ref().get_string(name)
+    }
+    fn get_bytes(&self, name: &str) -> Result<Vec<u8>, ResourceErrorReason> {
+        self.as_ref().get_bytes(name)
+    }
+    fn get_token_iterator(
+        &self,
+        name: &str,
+    ) -> Result<Box<dyn TokenIterator>, ResourceErrorReason> {
+        self.as_ref().get_token_iterator(name)
+    }
+    fn write_stdout(&self, text: &str) -> Result<(), ResourceErrorReason> {
+        self.as_ref().write_stdout(text)
+    }
+    fn write_stderr(&self, text: &str) -> Result<(), ResourceErrorReason> {
+        self.as_ref().write_stderr(text)
+    }
+}
+
+/// name of the standard input resource
+pub const STDIN_RESOURCE_NAME: &str = "%STDIN";
+
+/// everything consumed from the standard input so far
+///
+/// The standard input can only be read once, so whatever leaves it is
+/// kept here and every later retrieval sees the same content. `eof`
+/// marks that the stream has been drained to the end.
+#[derive(Default)]
+struct StdinCache {
+    body: Vec<u8>,
+    eof: bool,
+}
+
+/// reader teeing everything it yields into the stdin cache
+///
+/// This backs the streaming `%STDIN` token iterator: input is read
+/// line by line as the underlying `BufReader` refills, so interactive
+/// input executes as it is entered, while the consumed bytes still
+/// end up in the cache for later retrievals.
+struct StdinTeeReader<R: Read> {
+    inner: R,
+    cache: Rc<RefCell<StdinCache>>,
+}
+impl<R: Read> Read for StdinTeeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let mut cache = self.cache.borrow_mut();
+        if n == 0 {
+            cache.eof = true;
+        } else {
+            cache.body.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// standard resource implementation backed by the process environment
+pub struct StdResources {
+    project_root: PathBuf,
+    resources: HashMap<String, String>,
+    byte_resources: HashMap<String, Vec<u8>>,
+    stdin_cache: Rc<RefCell<StdinCache>>,
+}
+impl StdResources {
+    /// create a new instance
+    ///
+    /// # Arguments
+    /// * `project_root` - base directory of `:path` resources
+    pub fn new(project_root: PathBuf) -> Self {
+        StdResources {
+            project_root,
+            resources: HashMap::new(),
+            byte_resources: HashMap::new(),
+            stdin_cache: Rc::new(RefCell::new(StdinCache::default())),
+        }
+    }
+    /// read the standard input to the end and cache the body
+    ///
+    /// Lines already consumed by a streaming token iterator are kept;
+    /// the remainder of the input is drained, so every later retrieval
+    /// sees the same content.
+    fn read_stdin_cached<R: Read>(
+        &self,
+        name: &str,
+        mut reader: R,
+    ) -> Result<String, ResourceErrorReason> {
+        let mut cache = self.stdin_cache.borrow_mut();
+        if !cache.eof {
+            reader
+                .read_to_end(&mut cache.body)
+                .map_err(|e| Self::map_io_error(name, e))?;
+            cache.eof = true;
+        }
+        String::from_utf8(cache.body.clone())
+            .map_err(|_| ResourceErrorReason::InvalidUtf8(String::from(name)))
+    }
+    /// register a named resource
+    ///
+    /// # Arguments
+    /// * `name` - resource name
+    /// * `value` - resource body
+    pub fn add_resource(&mut self, name: String, value: String) {
+        self.resources.insert(name, value);
+    }
+    /// register a named binary resource
+    ///
+    /// Byte resources are only visible through `get_bytes`; loading
+    /// one as a string or token stream attempts a UTF-8 decode and
+    /// fails with `InvalidUtf8` otherwise.
+    ///
+    /// # Arguments
+    /// * `name` - resource name
+    /// * `value` - resource body
+    pub fn add_bytes_resource(&mut self, name: String, value: Vec<u8>) {
+        self.byte_resources.insert(name, value);
+    }
+    /// resolve a `:path` resource below the project root
+    ///
+    /// The path is canonicalized and rejected when it escapes the
+    /// project root, e.g. through `..` components or symlinks.
+    fn resolve_path(&self, name: &str, path: &str) -> Result<PathBuf, ResourceErrorReason> {
+        let root = self
+            .project_root
+            .canonicalize()
+            .map_err(|e| Self::map_io_error(name, e))?;
+        let resolved = root
+            .join(path)
+            .canonicalize()
+            .map_err(|e| Self::map_io_error(name, e))?;
+        if resolved.starts_with(&root) {
+            Ok(resolved)
+        } else {
+            Err(ResourceErrorReason::PathEscape(String::from(name)))
+        }
+    }
+    /// map an I/O error on a named resource
+    fn map_io_error(name: &str, e: io::Error) -> ResourceErrorReason {
+        if e.kind() == io::ErrorKind::NotFound {
+            ResourceErrorReason::ResourceNotFound(String::from(name))
+        } else {
+            ResourceErrorReason::IOError(format!("{}: {}", name, e))
+        }
+    }
+}
+impl Resources for StdResources {
+    fn get_string(&self, name: &str) -> Result<String, ResourceErrorReason> {
+        if let Some(var_name) = name.strip_prefix('&') {
+            // an empty-but-set variable is a valid (empty) resource;
+            // only a truly unset variable is an error
+            match env::var(var_name) {
+                Ok(body) => Ok(body),
+                Err(env::VarError::NotPresent) => {
+                    Err(ResourceErrorReason::EnvVarNotSet(String::from(var_name)))
+                }
+                Err(e) => Err(ResourceErrorReason::IOError(format!("{}: {}", name, e))),
+            }
+        } else if name == STDIN_RESOURCE_NAME {
+            self.read_stdin_cached(name, io::stdin())
+        } else if name.starts_with(':') {
+            let bytes = self.get_bytes(name)?;
+            String::from_utf8(bytes)
+                .map_err(|_| ResourceErrorReason::InvalidUtf8(String::from(name)))
+        } else if let Some(body) = self.resources.get(name) {
+            Ok(body.clone())
+        } else if let Some(bytes) = self.byte_resources.get(name) {
+            String::from_utf8(bytes.clone())
+                .map_err(|_| ResourceErrorReason::InvalidUtf8(String::from(name)))
+        } else {
+            Err(ResourceErrorReason::ResourceNotFound(String::from(name)))
+        }
+    }
+    fn get_bytes(&self, name: &str) -> Result<Vec<u8>, ResourceErrorReason> {
+        if let Some(path) = name.strip_prefix(':') {
+            let mut body = Vec::new();
+            File::open(self.resolve_path(name, path)?)
+                .and_then(|mut f| f.read_to_end(&mut body))
+                .map_err(|e| Self::map_io_error(name, e))?;
+            Ok(body)
+        } else if let Some(bytes) = self.byte_resources.get(name) {
+            Ok(bytes.clone())
+        } else {
+            Ok(self.get_string(name)?.into_bytes())
+        }
+    }
+    fn get_token_iterator(
+        &self,
+        name: &str,
+    ) -> Result<Box<dyn TokenIterator>, ResourceErrorReason> {
+        if name == STDIN_RESOURCE_NAME {
+            // stream the input while it is still open so interactive
+            // sessions execute each line as it is entered; once it has
+            // been drained, replay the cached body
+            let cache = self.stdin_cache.borrow();
+            if cache.eof {
+                let body = String::from_utf8(cache.body.clone())
+                    .map_err(|_| ResourceErrorReason::InvalidUtf8(String::from(name)))?;
+                Ok(Box::new(new_token_stream_from_string(
+                    body,
+                    String::from(name),
+                )))
+            } else {
+                drop(cache);
+                let reader = StdinTeeReader {
+                    inner: io::stdin(),
+                    cache: Rc::clone(&self.stdin_cache),
+                };
+                Ok(Box::new(create_token_iterator(reader, String::from(name))))
+            }
+        } else if let Some(path) = name.strip_prefix(':') {
+            let file = File::open(self.resolve_path(name, path)?)
+                .map_err(|e| Self::map_io_error(name, e))?;
+            Ok(Box::new(create_token_iterator(file, String::from(name))))
+        } else {
+            let body = self.get_string(name)?;
+            Ok(Box::new(new_token_stream_from_string(
+                body,
+                String::from(name),
+            )))
+        }
+    }
+    fn write_stdout(&self, text: &str) -> Result<(), ResourceErrorReason> {
+        let mut out = io::stdout();
+        out.write_all(text.as_bytes())
+            .and_then(|_| out.flush())
+            .map_err(|e| ResourceErrorReason::IOError(e.to_string()))
+    }
+    fn write_stderr(&self, text: &str) -> Result<(), ResourceErrorReason> {
+        let mut out = io::stderr();
+        out.write_all(text.as_bytes())
+            .and_then(|_| out.flush())
+            .map_err(|e| ResourceErrorReason::IOError(e.to_string()))
+    }
+}
+
+/// signature of an output callback used by `CallbackResources`
+pub type OutputCallback = Box<dyn FnMut(&str)>;
+
+/// resource implementation routing output into host callbacks
+///
+/// Resource loading is delegated to an inner provider; everything
+/// written to the standard output or error is handed to the given
+/// closures, so a GUI or WASM host can route the text anywhere.
+pub struct CallbackResources<P: Resources> {
+    inner: P,
+    stdout: RefCell<OutputCallback>,
+    stderr: RefCell<OutputCallback>,
+}
+impl<P: Resources> CallbackResources<P> {
+    /// create a new instance
+    ///
+    /// # Arguments
+    /// * `inner` - provider resource names are resolved against
+    /// * `stdout` - receives every standard output write
+    /// * `stderr` - receives every standard error write
+    pub fn new(inner: P, stdout: OutputCallback, stderr: OutputCallback) -> Self {
+        CallbackResources {
+            inner,
+            stdout: RefCell::new(stdout),
+            stderr: RefCell::new(stderr),
+        }
+    }
+}
+impl<P: Resources> Resources for CallbackResources<P> {
+    fn get_string(&self, name: &str) -> Result<String, ResourceErrorReason> {
+        self.inner.get_string(name)
+    }
+    fn get_bytes(&self, name: &str) -> Result<Vec<u8>, ResourceErrorReason> {
+        self.inner.get_bytes(name)
+    }
+    fn get_token_iterator(
+        &self,
+        name: &str,
+    ) -> Result<Box<dyn TokenIterator>, ResourceErrorReason> {
+        self.inner.get_token_iterator(name)
+    }
+    fn write_stdout(&self, text: &str) -> Result<(), ResourceErrorReason> {
+        (self.stdout.borrow_mut())(text);
+        Ok(())
+    }
+    fn write_stderr(&self, text: &str) -> Result<(), ResourceErrorReason> {
+        (self.stderr.borrow_mut())(text);
+        Ok(())
+    }
+}
+
+/// resource implementation that keeps everything in memory
+///
+/// The output is collected into internal buffers, which makes this
+/// implementation useful for tests and for embedding.
+pub struct BufferResources {
+    resources: HashMap<String, String>,
+    stdout: RefCell<String>,
+    stderr: RefCell<String>,
+}
+impl BufferResources {
+    /// create a new instance
+    pub fn new() -> Self {
+        BufferResources {
+            resources: HashMap::new(),
+            stdout: RefCell::new(String::new()),
+            stderr: RefCell::new(String::new()),
+        }
+    }
+    /// register a named resource
+    pub fn add_resource(&mut self, name: String, value: String) {
+        self.resources.insert(name, value);
+    }
+    /// everything written to the standard output so far
+    pub fn stdout(&self) -> String {
+        self.stdout.borrow().clone()
+    }
+    /// everything written to the standard error so far
+    pub fn stderr(&self) -> String {
+        self.stderr.borrow().clone()
+    }
+}
+impl Default for BufferResources {
+    fn default() -> Self {
+        BufferResources::new()
+    }
+}
+impl Resources for BufferResources {
+    fn get_string(&self, name: &str) -> Result<String, ResourceErrorReason> {
+        self.resources
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ResourceErrorReason::ResourceNotFound(String::from(name)))
+    }
+    fn get_bytes(&self, name: &str) -> Result<Vec<u8>, ResourceErrorReason> {
+        Ok(self.get_string(name)?.into_bytes())
+    }
+    fn get_token_iterator(
+        &self,
+        name: &str,
+    ) -> Result<Box<dyn TokenIterator>, ResourceErrorReason> {
+        let body = self.get_string(name)?;
+        Ok(Box::new(new_token_stream_from_string(
+            body,
+            String::from(name),
+        )))
+    }
+    fn write_stdout(&self, text: &str) -> Result<(), ResourceErrorReason> {
+        self.stdout.borrow_mut().push_str(text);
+        Ok(())
+    }
+    fn write_stderr(&self, text: &str) -> Result<(), ResourceErrorReason> {
+        self.stderr.borrow_mut().push_str(text);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_resource() {
+        let mut r = StdResources::new(PathBuf::from("."));
+        r.add_resource(String::from("lib"), String::from("1 2 +"));
+        assert_eq!(r.get_string("lib").unwrap(), "1 2 +");
+        let mut i = r.get_token_iterator("lib").unwrap();
+        assert_eq!(i.script_name(), "lib");
+        assert!(i.next_token().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_resource_not_found() {
+        let r = StdResources::new(PathBuf::from("."));
+        assert_eq!(
+            r.get_string("missing").unwrap_err(),
+            ResourceErrorReason::ResourceNotFound(String::from("missing"))
+        );
+        assert_eq!(
+            r.get_string(":no/such/file").unwrap_err(),
+            ResourceErrorReason::ResourceNotFound(String::from(":no/such/file"))
+        );
+    }
+
+    #[test]
+    fn test_env_resource() {
+        env::set_var("EXST_RESOURCE_TEST", "abc");
+        let r = StdResources::new(PathBuf::from("."));
+        assert_eq!(r.get_string("&EXST_RESOURCE_TEST").unwrap(), "abc");
+        env::remove_var("EXST_RESOURCE_TEST");
+    }
+
+    #[test]
+    fn test_env_resource_empty_vs_unset() {
+        let r = StdResources::new(PathBuf::from("."));
+        env::set_var("EXST_RESOURCE_EMPTY_TEST", "");
+        assert_eq!(r.get_string("&EXST_RESOURCE_EMPTY_TEST").unwrap(), "");
+        env::remove_var("EXST_RESOURCE_EMPTY_TEST");
+        assert_eq!(
+            r.get_string("&EXST_RESOURCE_EMPTY_TEST").unwrap_err(),
+            ResourceErrorReason::EnvVarNotSet(String::from("EXST_RESOURCE_EMPTY_TEST"))
+        );
+        assert!(r.get_token_iterator("&EXST_RESOURCE_EMPTY_TEST").is_err());
+    }
+
+    #[test]
+    fn test_stdin_read_once() {
+        use std::io::Cursor;
+        let r = StdResources::new(PathBuf::from("."));
+        let first = r
+            .read_stdin_cached(STDIN_RESOURCE_NAME, Cursor::new("1 2 +"))
+            .unwrap();
+        assert_eq!(first, "1 2 +");
+        // the second reader is never consumed; the cache wins
+        let second = r
+            .read_stdin_cached(STDIN_RESOURCE_NAME, Cursor::new("ignored"))
+            .unwrap();
+        assert_eq!(second, "1 2 +");
+    }
+
+    #[test]
+    fn test_stdin_streaming_tee() {
+        use std::io::Cursor;
+        let r = StdResources::new(PathBuf::from("."));
+        let reader = StdinTeeReader {
+            inner: Cursor::new("1 2\n+\n"),
+            cache: Rc::clone(&r.stdin_cache),
+        };
+        let mut i = create_token_iterator(reader, String::from(STDIN_RESOURCE_NAME));
+        // consumed tokens are teed into the cache as they stream by
+        let t = i.next_token().unwrap().unwrap();
+        assert_eq!(
+            t.value_token,
+            crate::lang::tokenizer::ValueToken::IntValue(1)
+        );
+        assert!(!r.stdin_cache.borrow().body.is_empty());
+        while i.next_token().unwrap().is_some() {}
+        assert!(r.stdin_cache.borrow().eof);
+        // later retrievals replay exactly what was consumed
+        assert_eq!(r.get_string(STDIN_RESOURCE_NAME).unwrap(), "1 2\n+\n");
+        let mut again = r.get_token_iterator(STDIN_RESOURCE_NAME).unwrap();
+        assert!(again.next_token().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_path_resource_stays_below_project_root() {
+        let root = env::temp_dir().join("exst_resource_path_test");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/lib.x"), "1 2 +").unwrap();
+        let r = StdResources::new(root.clone());
+        assert_eq!(r.get_string(":sub/lib.x").unwrap(), "1 2 +");
+        match r.get_string(":../exst_resource_path_test/sub/../../etc/passwd") {
+            Err(ResourceErrorReason::PathEscape(_))
+            | Err(ResourceErrorReason::ResourceNotFound(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        std::fs::write(root.join("escape.x"), "").unwrap();
+        std::fs::rename(root.join("escape.x"), env::temp_dir().join("exst_escape.x")).unwrap();
+        assert_eq!(
+            r.get_string(":../exst_escape.x").unwrap_err(),
+            ResourceErrorReason::PathEscape(String::from(":../exst_escape.x"))
+        );
+        std::fs::remove_file(env::temp_dir().join("exst_escape.x")).unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_bytes_resource() {
+        let mut r = StdResources::new(PathBuf::from("."));
+        r.add_bytes_resource(String::from("blob"), vec![0u8, 0xff]);
+        r.add_bytes_resource(String::from("text"), b"1 2 +".to_vec());
+        assert_eq!(r.get_bytes("blob").unwrap(), vec![0u8, 0xff]);
+        assert_eq!(
+            r.get_string("blob").unwrap_err(),
+            ResourceErrorReason::InvalidUtf8(String::from("blob"))
+        );
+        assert!(r.get_token_iterator("blob").is_err());
+        assert_eq!(r.get_string("text").unwrap(), "1 2 +");
+    }
+
+    #[test]
+    fn test_binary_resource() {
+        let root = env::temp_dir().join("exst_resource_bytes_test");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("blob.bin"), [0u8, 0xff, 0xfe, 1]).unwrap();
+        let r = StdResources::new(root.clone());
+        assert_eq!(r.get_bytes(":blob.bin").unwrap(), vec![0u8, 0xff, 0xfe, 1]);
+        assert_eq!(
+            r.get_string(":blob.bin").unwrap_err(),
+            ResourceErrorReason::InvalidUtf8(String::from(":blob.bin"))
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_buffer_resources() {
+        let mut r = BufferResources::new();
+        r.add_resource(String::from("main"), String::from("1"));
+        assert_eq!(r.get_string("main").unwrap(), "1");
+        r.write_stdout("out").unwrap();
+        r.write_stderr("err").unwrap();
+        assert_eq!(r.stdout(), "out");
+        assert_eq!(r.stderr(), "err");
+    }
+}
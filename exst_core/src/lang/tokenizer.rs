@@ -0,0 +1,1337 @@
+//! tokenizer
+//!
+//! Splits an input character stream into tokens.
+//! A token is one of the following.
+//!
+//! * integer literal: `123`, `-4`, `0xff`
+//! * float literal: `1.5`, `-0.25`
+//! * string literal: `"abc"` (with `\` escapes)
+//! * comment: `#` up to the end of the line
+//! * symbol: everything else up to the next separator
+//!
+//! Tokens are separated by space, tab, CR and LF.
+
+use super::VmInt;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+
+/// true if the character separates two tokens
+pub fn is_token_separator(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\r' || c == '\n'
+}
+
+/// reason of a tokenizer error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenizerErrorReason {
+    /// a string literal reached the end of input before the closing quote
+    StringLiteralIsNotClosed,
+    /// an I/O error occurred while reading the input
+    IOError(String),
+}
+
+/// tokenizer error with the source position where it occurred
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenizerError {
+    reason: TokenizerErrorReason,
+    line_number: usize,
+    column_number: usize,
+    end_line_number: Option<usize>,
+    end_column_number: Option<usize>,
+    script_name: Option<String>,
+}
+impl TokenizerError {
+    /// create a new error
+    ///
+    /// # Arguments
+    /// * `reason` - reason of the error
+    /// * `line_number` - line where the error occurred (1 origin)
+    /// * `column_number` - column where the error occurred (1 origin)
+    pub fn new(reason: TokenizerErrorReason, line_number: usize, column_number: usize) -> Self {
+        TokenizerError {
+            reason,
+            line_number,
+            column_number,
+            end_line_number: None,
+            end_column_number: None,
+            script_name: None,
+        }
+    }
+    /// record where the input ran out
+    ///
+    /// For an unterminated string literal the main position is the
+    /// opening quote; this adds the position where reading stopped.
+    pub fn with_end_position(mut self, line_number: usize, column_number: usize) -> Self {
+        self.end_line_number = Some(line_number);
+        self.end_column_number = Some(column_number);
+        self
+    }
+    /// record which script the error came from
+    ///
+    /// When several resources are tokenized the position alone is
+    /// ambiguous; the stream sets its script name here.
+    pub fn with_script_name(mut self, name: String) -> Self {
+        self.script_name = Some(name);
+        self
+    }
+    /// name of the script the error came from, when recorded
+    pub fn script_name(&self) -> Option<&String> {
+        self.script_name.as_ref()
+    }
+    /// reason of the error
+    pub fn reason(&self) -> &TokenizerErrorReason {
+        &self.reason
+    }
+    /// line where the error occurred
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+    /// column where the error occurred
+    pub fn column_number(&self) -> usize {
+        self.column_number
+    }
+    /// line where reading stopped, when recorded
+    pub fn end_line_number(&self) -> Option<usize> {
+        self.end_line_number
+    }
+    /// column where reading stopped, when recorded
+    pub fn end_column_number(&self) -> Option<usize> {
+        self.end_column_number
+    }
+}
+
+/// the value part of a token
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueToken {
+    /// integer literal
+    IntValue(VmInt),
+    /// float literal
+    FloatValue(f64),
+    /// string literal
+    StrValue(String),
+    /// anything else
+    Symbol(String),
+}
+
+/// a token with its source position
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    /// the value of the token
+    pub value_token: ValueToken,
+    /// line of the first character of the token (1 origin)
+    pub line_number: usize,
+    /// column of the first character of the token (1 origin)
+    pub column_number: usize,
+}
+impl Token {
+    /// create a new token
+    pub fn new(value_token: ValueToken, line_number: usize, column_number: usize) -> Self {
+        Token {
+            value_token,
+            line_number,
+            column_number,
+        }
+    }
+}
+
+/// a token or a comment
+///
+/// `next_token` drops comments, `next_token_with_comment` yields them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenWithComment {
+    /// a normal token
+    Token(Token),
+    /// a comment; the token value is a `StrValue` with the comment body
+    Comment(Token),
+}
+
+/// iterator like interface producing tokens from some input
+/// callback fired just before a fresh input line is read
+pub type LineStartHook = Box<dyn FnMut()>;
+
+/// callback receiving each fully consumed input line
+pub type LineConsumedHook = Box<dyn FnMut(&str)>;
+
+pub trait TokenIterator {
+    /// name of the script this iterator reads from
+    fn script_name(&self) -> &str;
+    /// next token, or None at the end of input
+    fn next_token(&mut self) -> Result<Option<Token>, TokenizerError>;
+    /// install a hook fired just before a fresh line is read
+    ///
+    /// An interactive front end uses this to print its prompt before
+    /// the read blocks on the terminal. The default does nothing;
+    /// sources without line structure ignore the hook.
+    fn set_line_start_hook(&mut self, _hook: LineStartHook) {}
+    /// install a hook receiving each input line once it is consumed
+    ///
+    /// An interactive front end uses this to record its input
+    /// history as it is entered. The default does nothing.
+    fn set_line_consumed_hook(&mut self, _hook: LineConsumedHook) {}
+    /// consume raw characters up to (and including) `end`,
+    /// returning the consumed characters without `end`
+    fn skip(&mut self, end: char) -> Result<String, TokenizerError>;
+    /// next token or comment, or None at the end of input
+    ///
+    /// The default implementation never yields a comment; sources
+    /// that keep comments around (like `TokenStream`) override this.
+    fn next_token_with_comment(&mut self) -> Result<Option<TokenWithComment>, TokenizerError> {
+        Ok(self.next_token()?.map(TokenWithComment::Token))
+    }
+    /// adapt this iterator into a plain `Iterator` over `Token`s
+    ///
+    /// A tokenizer error ends the iteration after one token whose
+    /// symbol body describes the error.
+    fn tokens(self) -> Tokens<Self>
+    where
+        Self: Sized,
+    {
+        Tokens {
+            inner: self,
+            done: false,
+        }
+    }
+}
+
+/// iterator over the tokens of a `TokenIterator`, for external
+/// parsers that do not care about the `Result` plumbing
+pub struct Tokens<I: TokenIterator> {
+    inner: I,
+    done: bool,
+}
+impl<I: TokenIterator> Iterator for Tokens<I> {
+    type Item = Token;
+    fn next(&mut self) -> Option<Token> {
+        if self.done {
+            return None;
+        }
+        match self.inner.next_token() {
+            Ok(Some(token)) => Some(token),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Token::new(
+                    ValueToken::Symbol(format!("<error: {:?}>", e.reason())),
+                    e.line_number(),
+                    e.column_number(),
+                ))
+            }
+        }
+    }
+}
+
+/// a token iterator over no input at all
+///
+/// Used as the initial input of the VM before any script is loaded.
+pub struct EmptyTokenStream {
+    name: String,
+}
+impl EmptyTokenStream {
+    /// create an empty stream
+    pub fn new() -> Self {
+        EmptyTokenStream {
+            name: String::from("###DUMMY###"),
+        }
+    }
+    /// create an empty stream reporting the given script name
+    pub fn with_name(name: String) -> Self {
+        EmptyTokenStream { name }
+    }
+}
+impl Default for EmptyTokenStream {
+    fn default() -> Self {
+        EmptyTokenStream::new()
+    }
+}
+impl TokenIterator for EmptyTokenStream {
+    fn script_name(&self) -> &str {
+        &self.name
+    }
+    fn next_token(&mut self) -> Result<Option<Token>, TokenizerError> {
+        Ok(None)
+    }
+    fn skip(&mut self, _end: char) -> Result<String, TokenizerError> {
+        Ok(String::new())
+    }
+}
+
+/// character stream over a buffered reader
+///
+/// Reads the input one line at a time and yields the characters of the line.
+/// On an I/O error (including invalid UTF-8) the error is yielded once and
+/// the iteration stops.
+pub struct CharStreamFromBufRead<R: BufRead> {
+    reader: R,
+    bytes: Vec<u8>,
+    line: String,
+    /// byte offset of the next character in `line`
+    position: usize,
+    /// bytes of the input converted in previous lines
+    consumed: u64,
+    failed: bool,
+}
+impl<R: BufRead> CharStreamFromBufRead<R> {
+    /// create a new stream over a buffered reader
+    pub fn new(reader: R) -> Self {
+        CharStreamFromBufRead {
+            reader,
+            bytes: Vec::new(),
+            line: String::new(),
+            position: 0,
+            consumed: 0,
+            failed: false,
+        }
+    }
+}
+impl<R: BufRead> Iterator for CharStreamFromBufRead<R> {
+    type Item = io::Result<char>;
+    fn next(&mut self) -> Option<io::Result<char>> {
+        if self.failed {
+            return None;
+        }
+        while self.position >= self.line.len() {
+            // both buffers are reused, so a long input allocates
+            // only when a line outgrows every previous one
+            self.bytes.clear();
+            match self.reader.read_until(b'\n', &mut self.bytes) {
+                Ok(0) => return None,
+                Ok(n) => match std::str::from_utf8(&self.bytes) {
+                    Ok(s) => {
+                        self.line.clear();
+                        self.line.push_str(s);
+                        self.position = 0;
+                        self.consumed += n as u64;
+                    }
+                    Err(e) => {
+                        self.failed = true;
+                        let offset = self.consumed + e.valid_up_to() as u64;
+                        return Some(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("invalid UTF-8 at byte {}", offset),
+                        )));
+                    }
+                },
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        let c = self.line[self.position..].chars().next()?;
+        self.position += c.len_utf8();
+        Some(Ok(c))
+    }
+}
+
+/// character stream over an in memory string
+pub struct CharStreamFromString {
+    chars: Vec<char>,
+    position: usize,
+}
+impl CharStreamFromString {
+    /// create a new stream over the given body
+    pub fn new(body: String) -> Self {
+        CharStreamFromString {
+            chars: body.chars().collect(),
+            position: 0,
+        }
+    }
+}
+impl Iterator for CharStreamFromString {
+    type Item = io::Result<char>;
+    fn next(&mut self) -> Option<io::Result<char>> {
+        if self.position < self.chars.len() {
+            let c = self.chars[self.position];
+            self.position += 1;
+            Some(Ok(c))
+        } else {
+            None
+        }
+    }
+}
+
+/// character stream with one token worth of push back and position tracking
+///
+/// Pushed back characters are read again before the underlying stream,
+/// in the order they were pushed:
+///
+/// ```
+/// use exst_core::lang::tokenizer::CharStreamFromString;
+/// use exst_core::lang::tokenizer::InputCharStream;
+///
+/// let mut s = InputCharStream::new(Box::new(CharStreamFromString::new(String::from("c"))));
+/// s.push('a');
+/// s.push('b');
+/// assert_eq!(s.next().unwrap(), Some('a'));
+/// assert_eq!(s.next().unwrap(), Some('b'));
+/// assert_eq!(s.next().unwrap(), Some('c'));
+/// ```
+pub struct InputCharStream {
+    stream: Box<dyn Iterator<Item = io::Result<char>>>,
+    lookahead: std::collections::VecDeque<char>,
+    line_number: usize,
+    column_number: usize,
+    tab_width: usize,
+    eof: bool,
+    line_start_hook: Option<LineStartHook>,
+    /// true when the next character pulled from the stream starts a line
+    at_line_start: bool,
+    line_consumed_hook: Option<LineConsumedHook>,
+    /// characters of the line being consumed, kept for the hook
+    current_line: String,
+}
+impl InputCharStream {
+    /// create a new stream
+    pub fn new(stream: Box<dyn Iterator<Item = io::Result<char>>>) -> Self {
+        InputCharStream {
+            stream,
+            lookahead: std::collections::VecDeque::new(),
+            line_number: 1,
+            column_number: 1,
+            tab_width: 1,
+            eof: false,
+            line_start_hook: None,
+            at_line_start: true,
+            line_consumed_hook: None,
+            current_line: String::new(),
+        }
+    }
+    /// install a hook fired just before a fresh line is read
+    ///
+    /// The hook runs before the underlying stream is asked for the
+    /// first character of a line, so with a blocking input (like a
+    /// terminal) it runs before the read blocks.
+    pub fn set_line_start_hook(&mut self, hook: LineStartHook) {
+        self.line_start_hook = Some(hook);
+    }
+    /// install a hook receiving each input line once it is consumed
+    ///
+    /// The hook runs when the line feed closing a line is pulled from
+    /// the underlying stream, and once more at the end of input when
+    /// a final line has no line feed. The line is passed without its
+    /// line feed.
+    pub fn set_line_consumed_hook(&mut self, hook: LineConsumedHook) {
+        self.line_consumed_hook = Some(hook);
+    }
+    /// how many columns a tab advances (1 by default)
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width;
+    }
+    /// line of the next character (1 origin)
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+    /// column of the next character (1 origin)
+    pub fn column_number(&self) -> usize {
+        self.column_number
+    }
+    /// push back a character
+    ///
+    /// Note: pushing back a line feed does not rewind the line counter.
+    pub fn push(&mut self, c: char) {
+        self.lookahead.push_back(c);
+        if self.column_number > 1 {
+            self.column_number -= 1;
+        }
+    }
+    /// next character, or None at the end of input
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> io::Result<Option<char>> {
+        let c = if let Some(c) = self.lookahead.pop_front() {
+            Some(c)
+        } else if self.eof {
+            None
+        } else {
+            if self.at_line_start {
+                if let Some(hook) = self.line_start_hook.as_mut() {
+                    hook();
+                }
+            }
+            match self.stream.next() {
+                None => {
+                    self.eof = true;
+                    if let Some(hook) = self.line_consumed_hook.as_mut() {
+                        // a final line without a line feed still counts
+                        if !self.current_line.is_empty() {
+                            hook(&self.current_line);
+                            self.current_line.clear();
+                        }
+                    }
+                    None
+                }
+                Some(Ok(c)) => {
+                    if let Some(hook) = self.line_consumed_hook.as_mut() {
+                        if c == '\n' {
+                            hook(&self.current_line);
+                            self.current_line.clear();
+                        } else {
+                            self.current_line.push(c);
+                        }
+                    }
+                    Some(c)
+                }
+                Some(Err(e)) => {
+                    self.eof = true;
+                    return Err(e);
+                }
+            }
+        };
+        if let Some(c) = c {
+            self.at_line_start = c == '\n';
+            if c == '\n' {
+                self.line_number += 1;
+                self.column_number = 1;
+            } else if c == '\t' {
+                self.column_number += self.tab_width;
+            } else {
+                self.column_number += 1;
+            }
+        }
+        Ok(c)
+    }
+}
+
+/// token stream over a character stream
+pub struct TokenStream {
+    script_name: String,
+    input: InputCharStream,
+    extra_separators: Vec<char>,
+    raw_string_delim: Option<char>,
+    escape_char: Option<char>,
+}
+impl TokenStream {
+    /// create a new token stream
+    ///
+    /// # Arguments
+    /// * `script_name` - name used in error reports and debug info
+    /// * `input` - character input
+    pub fn new(script_name: String, input: InputCharStream) -> Self {
+        TokenStream {
+            script_name,
+            input,
+            extra_separators: Vec::new(),
+            raw_string_delim: None,
+            escape_char: Some('\\'),
+        }
+    }
+
+    /// extend the separator set with extra characters
+    ///
+    /// The default separators always remain in effect.
+    pub fn with_separators(mut self, extra: &[char]) -> Self {
+        self.extra_separators.extend_from_slice(extra);
+        self
+    }
+
+    /// count a tab as the given number of columns in positions
+    ///
+    /// The default of one keeps historic column reporting; editors
+    /// rendering 4- or 8-wide tabs can align error carets with this.
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.input.set_tab_width(tab_width);
+        self
+    }
+
+    /// treat the given character as a raw string delimiter
+    ///
+    /// A raw string keeps its text verbatim; `\\` has no special
+    /// meaning and the literal ends at the next delimiter.
+    pub fn with_raw_string_delim(mut self, c: char) -> Self {
+        self.raw_string_delim = Some(c);
+        self
+    }
+
+    /// change the escape character of string literals, or disable
+    /// escape processing entirely with `None`
+    ///
+    /// The default is `\\`. Sources dense with backslashes (regexes,
+    /// Windows paths) can move the escape out of the way or turn
+    /// every string into a raw one.
+    pub fn with_escape_char(mut self, c: Option<char>) -> Self {
+        self.escape_char = c;
+        self
+    }
+
+    /// true if the character separates two tokens in this stream
+    fn is_separator(&self, c: char) -> bool {
+        is_token_separator(c) || self.extra_separators.contains(&c)
+    }
+
+    /// convert a number body to an integer in the given radix
+    ///
+    /// Returns None when the body contains an invalid digit or
+    /// the value does not fit in the machine integer width.
+    pub fn convert_number(body: &str, radix: u32) -> Option<VmInt> {
+        let (negative, digits) = match body.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, body),
+        };
+        if digits.is_empty() {
+            return None;
+        }
+        let mut acc: i128 = 0;
+        for c in digits.chars() {
+            let d = c.to_digit(radix)?;
+            acc = acc.checked_mul(i128::from(radix))?.checked_add(i128::from(d))?;
+            if acc > i128::from(VmInt::MAX) + 1 {
+                return None;
+            }
+        }
+        let acc = if negative { -acc } else { acc };
+        use std::convert::TryFrom;
+        VmInt::try_from(acc).ok()
+    }
+
+    /// convert a numeric body with an explicit type suffix
+    ///
+    /// `3i` forces an integer, `3f` a float and `3u` an unsigned
+    /// reading reinterpreted in the machine integer width. A suffix
+    /// on a non-numeric body yields None, degrading the token to a
+    /// symbol.
+    fn convert_suffixed(body: &str) -> Option<ValueToken> {
+        if let Some(rest) = body.strip_suffix('i') {
+            return Self::convert_number(rest, 10).map(ValueToken::IntValue);
+        }
+        if let Some(rest) = body.strip_suffix('f') {
+            if let Some(i) = Self::convert_number(rest, 10) {
+                return Some(ValueToken::FloatValue(i as f64));
+            }
+            if rest.contains('.') {
+                return rest.parse::<f64>().ok().map(ValueToken::FloatValue);
+            }
+            return None;
+        }
+        if let Some(rest) = body.strip_suffix('u') {
+            #[cfg(not(feature = "int64"))]
+            return rest.parse::<u32>().ok().map(|v| ValueToken::IntValue(v as VmInt));
+            #[cfg(feature = "int64")]
+            return rest.parse::<u64>().ok().map(|v| ValueToken::IntValue(v as VmInt));
+        }
+        None
+    }
+
+    /// convert a token body to a value token
+    fn to_value_token(body: String) -> ValueToken {
+        let (digits, radix) = match (body.strip_prefix("0x"), body.strip_prefix("-0x")) {
+            (Some(rest), _) => (rest.to_string(), 16),
+            (_, Some(rest)) => (format!("-{}", rest), 16),
+            _ => (body.clone(), 10),
+        };
+        if let Some(i) = Self::convert_number(&digits, radix) {
+            ValueToken::IntValue(i)
+        } else if body.contains('.') && body.parse::<f64>().is_ok() {
+            ValueToken::FloatValue(body.parse::<f64>().unwrap())
+        } else if let Some(v) = Self::convert_suffixed(&body) {
+            v
+        } else {
+            ValueToken::Symbol(body)
+        }
+    }
+
+    /// wrap an I/O error at the current position
+    fn io_error(&self, e: io::Error) -> TokenizerError {
+        TokenizerError::new(
+            TokenizerErrorReason::IOError(e.to_string()),
+            self.input.line_number(),
+            self.input.column_number(),
+        )
+        .with_script_name(self.script_name.clone())
+    }
+
+    /// consume consecutive separators
+    fn consume_separator(&mut self) -> Result<(), TokenizerError> {
+        loop {
+            match self.input.next().map_err(|e| self.io_error(e))? {
+                None => break,
+                Some(c) if self.is_separator(c) => continue,
+                Some(c) => {
+                    self.input.push(c);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// consume a comment body up to the end of the line
+    fn parse_comment(&mut self) -> Result<String, TokenizerError> {
+        let mut body = String::new();
+        loop {
+            match self.input.next().map_err(|e| self.io_error(e))? {
+                None | Some('\n') => break,
+                Some(c) => body.push(c),
+            }
+        }
+        Ok(body)
+    }
+
+    /// decode one escaped character of a string literal
+    fn parse_string_internal_next_char(c: char) -> char {
+        match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            c => c,
+        }
+    }
+
+    /// consume a string literal body; the opening quote is already consumed
+    ///
+    /// # Arguments
+    /// * `end` - closing quote character
+    /// * `line_number` - position of the opening quote, for error reports
+    /// * `column_number` - position of the opening quote, for error reports
+    fn parse_string(
+        &mut self,
+        end: char,
+        line_number: usize,
+        column_number: usize,
+    ) -> Result<String, TokenizerError> {
+        let mut body = String::new();
+        loop {
+            match self.input.next().map_err(|e| self.io_error(e))? {
+                None => {
+                    return Err(TokenizerError::new(
+                        TokenizerErrorReason::StringLiteralIsNotClosed,
+                        line_number,
+                        column_number,
+                    )
+                    .with_end_position(self.input.line_number(), self.input.column_number())
+                    .with_script_name(self.script_name.clone()));
+                }
+                Some(c) if self.escape_char == Some(c) => {
+                    match self.input.next().map_err(|e| self.io_error(e))? {
+                        None => {
+                            return Err(TokenizerError::new(
+                                TokenizerErrorReason::StringLiteralIsNotClosed,
+                                line_number,
+                                column_number,
+                            )
+                            .with_end_position(
+                                self.input.line_number(),
+                                self.input.column_number(),
+                            )
+                            .with_script_name(self.script_name.clone()));
+                        }
+                        Some(c) => body.push(Self::parse_string_internal_next_char(c)),
+                    }
+                }
+                Some(c) if c == end => break,
+                Some(c) => body.push(c),
+            }
+        }
+        Ok(body)
+    }
+
+    /// consume a raw string literal body; the opening delimiter is
+    /// already consumed and escapes are kept verbatim
+    fn parse_raw_string(
+        &mut self,
+        end: char,
+        line_number: usize,
+        column_number: usize,
+    ) -> Result<String, TokenizerError> {
+        let mut body = String::new();
+        loop {
+            match self.input.next().map_err(|e| self.io_error(e))? {
+                None => {
+                    return Err(TokenizerError::new(
+                        TokenizerErrorReason::StringLiteralIsNotClosed,
+                        line_number,
+                        column_number,
+                    )
+                    .with_end_position(self.input.line_number(), self.input.column_number())
+                    .with_script_name(self.script_name.clone()));
+                }
+                Some(c) if c == end => break,
+                Some(c) => body.push(c),
+            }
+        }
+        Ok(body)
+    }
+
+    /// consume a number or symbol body up to the next separator
+    fn parse_number_body(&mut self, first: char) -> Result<String, TokenizerError> {
+        let mut body = String::new();
+        body.push(first);
+        loop {
+            match self.input.next().map_err(|e| self.io_error(e))? {
+                None => break,
+                Some(c) if self.is_separator(c) => break,
+                Some(c) => body.push(c),
+            }
+        }
+        Ok(body)
+    }
+
+    /// next token or comment, or None at the end of input
+    pub fn next_token_with_comment(
+        &mut self,
+    ) -> Result<Option<TokenWithComment>, TokenizerError> {
+        self.consume_separator()?;
+        let line_number = self.input.line_number();
+        let column_number = self.input.column_number();
+        match self.input.next().map_err(|e| self.io_error(e))? {
+            None => Ok(None),
+            Some('#') => {
+                let body = self.parse_comment()?;
+                Ok(Some(TokenWithComment::Comment(Token::new(
+                    ValueToken::StrValue(body),
+                    line_number,
+                    column_number,
+                ))))
+            }
+            Some('\\') => {
+                // `\` only opens a comment when followed by whitespace;
+                // a bare `\name` stays an ordinary symbol
+                match self.input.next().map_err(|e| self.io_error(e))? {
+                    None => Ok(Some(TokenWithComment::Comment(Token::new(
+                        ValueToken::StrValue(String::new()),
+                        line_number,
+                        column_number,
+                    )))),
+                    Some(c) if self.is_separator(c) => {
+                        self.input.push(c);
+                        let body = self.parse_comment()?;
+                        Ok(Some(TokenWithComment::Comment(Token::new(
+                            ValueToken::StrValue(body),
+                            line_number,
+                            column_number,
+                        ))))
+                    }
+                    Some(c) => {
+                        self.input.push(c);
+                        let body = self.parse_number_body('\\')?;
+                        Ok(Some(TokenWithComment::Token(Token::new(
+                            Self::to_value_token(body),
+                            line_number,
+                            column_number,
+                        ))))
+                    }
+                }
+            }
+            Some('"') => {
+                let body = self.parse_string('"', line_number, column_number)?;
+                Ok(Some(TokenWithComment::Token(Token::new(
+                    ValueToken::StrValue(body),
+                    line_number,
+                    column_number,
+                ))))
+            }
+            Some(c) if self.raw_string_delim == Some(c) => {
+                let body = self.parse_raw_string(c, line_number, column_number)?;
+                Ok(Some(TokenWithComment::Token(Token::new(
+                    ValueToken::StrValue(body),
+                    line_number,
+                    column_number,
+                ))))
+            }
+            Some(c) => {
+                let body = self.parse_number_body(c)?;
+                Ok(Some(TokenWithComment::Token(Token::new(
+                    Self::to_value_token(body),
+                    line_number,
+                    column_number,
+                ))))
+            }
+        }
+    }
+}
+impl TokenIterator for TokenStream {
+    fn script_name(&self) -> &str {
+        &self.script_name
+    }
+    fn set_line_start_hook(&mut self, hook: LineStartHook) {
+        self.input.set_line_start_hook(hook);
+    }
+    fn set_line_consumed_hook(&mut self, hook: LineConsumedHook) {
+        self.input.set_line_consumed_hook(hook);
+    }
+    fn next_token(&mut self) -> Result<Option<Token>, TokenizerError> {
+        loop {
+            match TokenStream::next_token_with_comment(self)? {
+                None => return Ok(None),
+                Some(TokenWithComment::Token(t)) => return Ok(Some(t)),
+                Some(TokenWithComment::Comment(_)) => continue,
+            }
+        }
+    }
+    fn next_token_with_comment(&mut self) -> Result<Option<TokenWithComment>, TokenizerError> {
+        TokenStream::next_token_with_comment(self)
+    }
+    fn skip(&mut self, end: char) -> Result<String, TokenizerError> {
+        let mut body = String::new();
+        loop {
+            match self.input.next().map_err(|e| self.io_error(e))? {
+                None => break,
+                Some(c) if c == end => break,
+                Some(c) => body.push(c),
+            }
+        }
+        Ok(body)
+    }
+}
+
+/// create a token iterator over an arbitrary reader
+///
+/// # Arguments
+/// * `reader` - input to tokenize
+/// * `script_name` - name used in error reports and debug info
+pub fn create_token_iterator<R: Read + 'static>(reader: R, script_name: String) -> TokenStream {
+    create_token_iterator_buffered(BufReader::new(reader), script_name)
+}
+
+/// create a token iterator over an already buffered reader
+///
+/// Unlike `create_token_iterator` this does not wrap the reader in
+/// another `BufReader`.
+///
+/// # Arguments
+/// * `reader` - buffered input to tokenize
+/// * `script_name` - name used in error reports and debug info
+pub fn create_token_iterator_buffered<R: BufRead + 'static>(
+    reader: R,
+    script_name: String,
+) -> TokenStream {
+    let stream = CharStreamFromBufRead::new(reader);
+    TokenStream::new(script_name, InputCharStream::new(Box::new(stream)))
+}
+
+/// create a token iterator over an in memory string
+///
+/// # Arguments
+/// * `body` - input to tokenize
+/// * `script_name` - name used in error reports and debug info
+pub fn new_token_stream_from_string(body: String, script_name: String) -> TokenStream {
+    TokenStream::new(
+        script_name,
+        InputCharStream::new(Box::new(CharStreamFromString::new(body))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn stream(body: &str) -> TokenStream {
+        new_token_stream_from_string(String::from(body), String::from("test"))
+    }
+
+    #[test]
+    fn test_push_back_many() {
+        let mut s = InputCharStream::new(Box::new(CharStreamFromString::new(String::new())));
+        for i in 0..1000u32 {
+            s.push(char::from_u32('a' as u32 + (i % 26)).unwrap());
+        }
+        for i in 0..1000u32 {
+            let expected = char::from_u32('a' as u32 + (i % 26)).unwrap();
+            assert_eq!(s.next().unwrap(), Some(expected));
+        }
+        assert_eq!(s.next().unwrap(), None);
+    }
+
+    #[test]
+    fn test_line_start_hook() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        let fired = Rc::new(RefCell::new(0usize));
+        let counter = Rc::clone(&fired);
+        let mut s = stream("1 2\n3 4");
+        s.set_line_start_hook(Box::new(move || *counter.borrow_mut() += 1));
+        // the hook runs before the first line is pulled in
+        assert!(s.next_token().unwrap().is_some());
+        assert_eq!(*fired.borrow(), 1);
+        // and again before the second line, not per token
+        assert!(s.next_token().unwrap().is_some());
+        assert_eq!(*fired.borrow(), 1);
+        assert!(s.next_token().unwrap().is_some());
+        assert_eq!(*fired.borrow(), 2);
+        assert!(s.next_token().unwrap().is_some());
+        assert!(s.next_token().unwrap().is_none());
+        assert_eq!(*fired.borrow(), 2);
+    }
+
+    #[test]
+    fn test_raw_string_delim() {
+        // a raw literal keeps its backslashes verbatim
+        let mut s = stream("`a\\nb`").with_raw_string_delim('`');
+        let t = s.next_token().unwrap().unwrap();
+        assert_eq!(t.value_token, ValueToken::StrValue(String::from("a\\nb")));
+        // the escaping double quote literal is unaffected
+        let mut s = stream("\"a\\nb\"").with_raw_string_delim('`');
+        let t = s.next_token().unwrap().unwrap();
+        assert_eq!(t.value_token, ValueToken::StrValue(String::from("a\nb")));
+        // an unclosed raw literal is reported like a normal one
+        let mut s = stream("`abc").with_raw_string_delim('`');
+        match s.next_token() {
+            Err(e) => assert_eq!(
+                *e.reason(),
+                TokenizerErrorReason::StringLiteralIsNotClosed
+            ),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_escape_char() {
+        // `%` takes over the role of `\`, which becomes an ordinary
+        // string character
+        let mut s = stream("\"a%nb\\c\"").with_escape_char(Some('%'));
+        let t = s.next_token().unwrap().unwrap();
+        assert_eq!(t.value_token, ValueToken::StrValue(String::from("a\nb\\c")));
+        // with escapes disabled every string is raw
+        let mut s = stream("\"a\\nb\"").with_escape_char(None);
+        let t = s.next_token().unwrap().unwrap();
+        assert_eq!(t.value_token, ValueToken::StrValue(String::from("a\\nb")));
+    }
+
+    #[test]
+    fn test_tab_width() {
+        // by default a tab advances the column by one
+        let mut s = stream("\ta");
+        let t = s.next_token().unwrap().unwrap();
+        assert_eq!(t.column_number, 2);
+        let mut s = stream("\ta").with_tab_width(4);
+        let t = s.next_token().unwrap().unwrap();
+        assert_eq!(t.value_token, ValueToken::Symbol(String::from("a")));
+        assert_eq!(t.column_number, 5);
+    }
+
+    #[test]
+    fn test_tokens_adapter() {
+        let tokens: Vec<Token> = stream("1 2\n+").tokens().collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new(ValueToken::IntValue(1), 1, 1),
+                Token::new(ValueToken::IntValue(2), 1, 3),
+                Token::new(ValueToken::Symbol(String::from("+")), 2, 1),
+            ]
+        );
+        // a tokenizer error becomes the last token
+        let tokens: Vec<Token> = stream("1 \"open").tokens().collect();
+        assert_eq!(tokens.len(), 2);
+        match &tokens[1].value_token {
+            ValueToken::Symbol(s) => assert!(s.starts_with("<error:")),
+            t => panic!("unexpected token: {:?}", t),
+        }
+    }
+
+    #[test]
+    fn test_tokenize_basic() {
+        let mut s = stream("1 -2 0xff abc \"s t\"");
+        assert_eq!(
+            s.next_token().unwrap().unwrap(),
+            Token::new(ValueToken::IntValue(1), 1, 1)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap(),
+            Token::new(ValueToken::IntValue(-2), 1, 3)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap(),
+            Token::new(ValueToken::IntValue(255), 1, 6)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap(),
+            Token::new(ValueToken::Symbol(String::from("abc")), 1, 11)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap(),
+            Token::new(ValueToken::StrValue(String::from("s t")), 1, 15)
+        );
+        assert_eq!(s.next_token().unwrap(), None);
+    }
+
+    #[test]
+    fn test_tokenize_float() {
+        let mut s = stream("1.5 -0.25 1.x");
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::FloatValue(1.5)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::FloatValue(-0.25)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::Symbol(String::from("1.x"))
+        );
+    }
+
+    #[test]
+    fn test_tokenize_type_suffix() {
+        let mut s = stream("3f 3i 3.5f 3x 4000000000u 0xff");
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::FloatValue(3.0)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(3)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::FloatValue(3.5)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::Symbol(String::from("3x"))
+        );
+        // the unsigned reading is reinterpreted in the machine width
+        #[cfg(not(feature = "int64"))]
+        let unsigned = 4000000000u32 as i32 as VmInt;
+        #[cfg(feature = "int64")]
+        let unsigned = 4000000000;
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(unsigned)
+        );
+        // hex literals ending in f are still plain integers
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(255)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "int64")]
+    fn test_tokenize_wide_int() {
+        let mut s = stream("4294967296 -9223372036854775808 0xffffffffff");
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(4294967296)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(i64::MIN)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(0xffffffffff)
+        );
+    }
+
+    #[test]
+    fn test_tokenize_lines() {
+        let mut s = stream("a\n  b");
+        let t = s.next_token().unwrap().unwrap();
+        assert_eq!((t.line_number, t.column_number), (1, 1));
+        let t = s.next_token().unwrap().unwrap();
+        assert_eq!((t.line_number, t.column_number), (2, 3));
+    }
+
+    #[test]
+    fn test_comment() {
+        let mut s = stream("1 # comment\n2");
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(1)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(2)
+        );
+        let mut s = stream("# comment");
+        match s.next_token_with_comment().unwrap().unwrap() {
+            TokenWithComment::Comment(t) => {
+                assert_eq!(t.value_token, ValueToken::StrValue(String::from(" comment")));
+            }
+            t => panic!("unexpected token: {:?}", t),
+        }
+    }
+
+    #[test]
+    fn test_backslash_comment() {
+        let mut s = stream("1 \\ this is a comment\n2");
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(1)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(2)
+        );
+        let mut s = stream("\\ comment");
+        match s.next_token_with_comment().unwrap().unwrap() {
+            TokenWithComment::Comment(t) => {
+                assert_eq!(t.value_token, ValueToken::StrValue(String::from(" comment")));
+            }
+            t => panic!("unexpected token: {:?}", t),
+        }
+        // a bare backslash must stay an ordinary symbol
+        let mut s = stream("\\foo");
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::Symbol(String::from("\\foo"))
+        );
+    }
+
+    #[test]
+    fn test_comment_position() {
+        let mut s = stream("x\n  #hi");
+        s.next_token().unwrap();
+        match s.next_token_with_comment().unwrap().unwrap() {
+            TokenWithComment::Comment(t) => {
+                assert_eq!(t.value_token, ValueToken::StrValue(String::from("hi")));
+                assert_eq!(t.line_number, 2);
+                // the position is the `#` itself, not the first body char
+                assert_eq!(t.column_number, 3);
+            }
+            t => panic!("unexpected token: {:?}", t),
+        }
+    }
+
+    #[test]
+    fn test_extra_separators() {
+        let mut s = stream("1,2,3").with_separators(&[',']);
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(1)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(2)
+        );
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::IntValue(3)
+        );
+        assert_eq!(s.next_token().unwrap(), None);
+        // without extras the comma is part of the symbol
+        let mut s = stream("1,2");
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::Symbol(String::from("1,2"))
+        );
+    }
+
+    #[test]
+    fn test_string_escape() {
+        let mut s = stream("\"a\\nb\\\"c\"");
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::StrValue(String::from("a\nb\"c"))
+        );
+    }
+
+    #[test]
+    fn test_string_not_closed() {
+        let mut s = stream("  \"abc");
+        let e = s.next_token().unwrap_err();
+        assert_eq!(e.reason(), &TokenizerErrorReason::StringLiteralIsNotClosed);
+        assert_eq!(e.line_number(), 1);
+        assert_eq!(e.column_number(), 3);
+    }
+
+    #[test]
+    fn test_string_not_closed_end_position() {
+        let mut s = stream("\"ab\nc\nd");
+        let e = s.next_token().unwrap_err();
+        assert_eq!(e.reason(), &TokenizerErrorReason::StringLiteralIsNotClosed);
+        // the main position is the opening quote
+        assert_eq!((e.line_number(), e.column_number()), (1, 1));
+        // the end position is where the input ran out
+        assert_eq!(e.end_line_number(), Some(3));
+        assert_eq!(e.end_column_number(), Some(2));
+    }
+
+    #[test]
+    fn test_io_error() {
+        let bytes: Vec<u8> = vec![b'a', 0xff, b'b'];
+        let mut s = create_token_iterator(Cursor::new(bytes), String::from("test"));
+        match s.next_token() {
+            Err(e) => match e.reason() {
+                TokenizerErrorReason::IOError(_) => {}
+                r => panic!("unexpected reason: {:?}", r),
+            },
+            t => panic!("unexpected token: {:?}", t),
+        }
+        assert_eq!(s.next_token().unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_utf8_offset() {
+        let bytes: Vec<u8> = vec![b'o', b'k', b'\n', b'a', 0xff, b'b'];
+        let mut s = CharStreamFromBufRead::new(BufReader::new(Cursor::new(bytes)));
+        assert_eq!(s.next().unwrap().unwrap(), 'o');
+        assert_eq!(s.next().unwrap().unwrap(), 'k');
+        assert_eq!(s.next().unwrap().unwrap(), '\n');
+        // the error reports the absolute offset of the first bad byte
+        match s.next() {
+            Some(Err(e)) => {
+                assert!(e.to_string().contains("byte 4"), "unexpected error: {}", e)
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // iteration stops at the first error
+        assert!(s.next().is_none());
+    }
+
+    #[test]
+    fn test_buffered_token_iterator() {
+        let body = "1 two \"three\"";
+        let mut buffered = create_token_iterator_buffered(
+            BufReader::new(Cursor::new(body)),
+            String::from("test"),
+        );
+        let mut plain = create_token_iterator(Cursor::new(body), String::from("test"));
+        loop {
+            let a = buffered.next_token().unwrap();
+            let b = plain.next_token().unwrap();
+            assert_eq!(a, b);
+            if a.is_none() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_buffered_large_input_matches_string_stream() {
+        // a large multi-line input tokenizes identically over the
+        // buffered reader and the in memory string
+        let mut body = String::new();
+        for i in 0..2000 {
+            body.push_str(&format!("word{} {} \"s{}\" 1.5\r\n", i, i, i));
+        }
+        let mut buffered = create_token_iterator_buffered(
+            BufReader::new(Cursor::new(body.clone())),
+            String::from("test"),
+        );
+        let mut plain = new_token_stream_from_string(body, String::from("test"));
+        loop {
+            let a = buffered.next_token().unwrap();
+            let b = plain.next_token().unwrap();
+            assert_eq!(a, b);
+            if a.is_none() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_skip() {
+        let mut s = stream("abc)def");
+        assert_eq!(s.skip(')').unwrap(), String::from("abc"));
+        assert_eq!(
+            s.next_token().unwrap().unwrap().value_token,
+            ValueToken::Symbol(String::from("def"))
+        );
+    }
+
+    #[test]
+    fn test_empty_token_stream() {
+        let mut s = EmptyTokenStream::new();
+        assert_eq!(s.script_name(), "###DUMMY###");
+        assert_eq!(s.next_token().unwrap(), None);
+        let s = EmptyTokenStream::with_name(String::from("<init>"));
+        assert_eq!(s.script_name(), "<init>");
+    }
+}
@@ -0,0 +1,349 @@
+//! buffer memory
+//!
+//! `BufferMemory` is the growable cell storage behind every stack and
+//! buffer of the VM. Addresses into the code and data buffers are
+//! represented by `CodeAddress` and `DataAddress`.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+/// reason of an address conversion error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferAddressErrorReason {
+    /// the address does not point at an entity
+    InvalidAddress,
+}
+impl fmt::Display for BufferAddressErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferAddressErrorReason::InvalidAddress => {
+                write!(f, "the address does not point at an entity")
+            }
+        }
+    }
+}
+impl std::error::Error for BufferAddressErrorReason {}
+
+/// an address into a buffer
+///
+/// `Root` points before the first entity and is used as a sentinel
+/// (for example as the return address of a top level call frame).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Address {
+    /// before the first entity
+    Root,
+    /// the entity at the given index
+    Entity(usize),
+}
+impl Address {
+    /// the address one entity after this one
+    pub fn next(self) -> Address {
+        match self {
+            Address::Root => Address::Entity(0),
+            Address::Entity(i) => Address::Entity(i + 1),
+        }
+    }
+}
+impl TryFrom<Address> for usize {
+    type Error = BufferAddressErrorReason;
+    fn try_from(a: Address) -> Result<usize, BufferAddressErrorReason> {
+        match a {
+            Address::Root => Err(BufferAddressErrorReason::InvalidAddress),
+            Address::Entity(i) => Ok(i),
+        }
+    }
+}
+
+/// an address into the code buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CodeAddress(pub Address);
+impl CodeAddress {
+    /// the address of the entity at the given index
+    pub fn from_index(i: usize) -> Self {
+        CodeAddress(Address::Entity(i))
+    }
+    /// the address one entity after this one
+    pub fn next(self) -> Self {
+        CodeAddress(self.0.next())
+    }
+}
+impl TryFrom<CodeAddress> for usize {
+    type Error = BufferAddressErrorReason;
+    fn try_from(a: CodeAddress) -> Result<usize, BufferAddressErrorReason> {
+        usize::try_from(a.0)
+    }
+}
+
+/// an address into the data buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DataAddress(pub Address);
+impl DataAddress {
+    /// the address of the entity at the given index
+    pub fn from_index(i: usize) -> Self {
+        DataAddress(Address::Entity(i))
+    }
+    /// the address one entity after this one
+    pub fn next(self) -> Self {
+        DataAddress(self.0.next())
+    }
+}
+impl TryFrom<DataAddress> for usize {
+    type Error = BufferAddressErrorReason;
+    fn try_from(a: DataAddress) -> Result<usize, BufferAddressErrorReason> {
+        usize::try_from(a.0)
+    }
+}
+
+/// reason of a buffer access error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferErrorReason {
+    /// access outside the buffer: (buffer length, accessed position)
+    OutOfRangeAccess(usize, usize),
+}
+impl fmt::Display for BufferErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BufferErrorReason::OutOfRangeAccess(len, position) => {
+                write!(f, "out of range: len={}, addr={}", len, position)
+            }
+        }
+    }
+}
+impl std::error::Error for BufferErrorReason {}
+
+/// growable cell storage
+///
+/// The top of a stack is the highest index. `pick` and `roll`
+/// address entities from the top, position 0 being the top itself.
+#[derive(Debug, Clone)]
+pub struct BufferMemory<T> {
+    buffer: Vec<T>,
+    max_seen: usize,
+}
+impl<T> BufferMemory<T> {
+    /// create an empty buffer
+    pub fn new() -> Self {
+        BufferMemory {
+            buffer: Vec::new(),
+            max_seen: 0,
+        }
+    }
+    /// number of entities in the buffer
+    pub fn here(&self) -> usize {
+        self.buffer.len()
+    }
+    /// largest number of entities the buffer ever held
+    pub fn peak(&self) -> usize {
+        self.max_seen
+    }
+    /// pre-size the buffer for at least the given number of
+    /// additional entities; the logical length is unchanged
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.reserve(additional);
+    }
+    /// push an entity on top
+    pub fn push(&mut self, v: T) {
+        self.buffer.push(v);
+        self.max_seen = self.max_seen.max(self.buffer.len());
+    }
+    /// pop the top entity
+    pub fn pop(&mut self) -> Result<T, BufferErrorReason> {
+        self.buffer
+            .pop()
+            .ok_or(BufferErrorReason::OutOfRangeAccess(0, 0))
+    }
+    /// entity at the given index (0 is the bottom)
+    pub fn get(&self, position: usize) -> Result<&T, BufferErrorReason> {
+        let len = self.buffer.len();
+        self.buffer
+            .get(position)
+            .ok_or(BufferErrorReason::OutOfRangeAccess(len, position))
+    }
+    /// mutable entity at the given index (0 is the bottom)
+    pub fn get_mut(&mut self, position: usize) -> Result<&mut T, BufferErrorReason> {
+        let len = self.buffer.len();
+        self.buffer
+            .get_mut(position)
+            .ok_or(BufferErrorReason::OutOfRangeAccess(len, position))
+    }
+    /// overwrite the entity at the given index (0 is the bottom)
+    pub fn set(&mut self, position: usize, v: T) -> Result<(), BufferErrorReason> {
+        *self.get_mut(position)? = v;
+        Ok(())
+    }
+    /// entity at the given position from the top (0 is the top)
+    pub fn pick(&self, position: usize) -> Result<&T, BufferErrorReason> {
+        let len = self.buffer.len();
+        if position < len {
+            Ok(&self.buffer[len - 1 - position])
+        } else {
+            Err(BufferErrorReason::OutOfRangeAccess(len, position))
+        }
+    }
+    /// move the entity at the given position from the top to the top
+    pub fn roll(&mut self, position: usize) -> Result<(), BufferErrorReason> {
+        let len = self.buffer.len();
+        if position < len {
+            let v = self.buffer.remove(len - 1 - position);
+            self.buffer.push(v);
+            Ok(())
+        } else {
+            Err(BufferErrorReason::OutOfRangeAccess(len, position))
+        }
+    }
+    /// remove the given number of entities from the top and return
+    /// them top-first
+    pub fn drain_top(&mut self, num: usize) -> Result<Vec<T>, BufferErrorReason> {
+        let len = self.buffer.len();
+        if num <= len {
+            let mut removed: Vec<T> = self.buffer.drain(len - num..).collect();
+            removed.reverse();
+            Ok(removed)
+        } else {
+            Err(BufferErrorReason::OutOfRangeAccess(len, num))
+        }
+    }
+    /// remove the given number of entities from the top
+    pub fn remove(&mut self, num: usize) -> Result<(), BufferErrorReason> {
+        let len = self.buffer.len();
+        if num <= len {
+            self.buffer.truncate(len - num);
+            Ok(())
+        } else {
+            Err(BufferErrorReason::OutOfRangeAccess(len, num))
+        }
+    }
+    /// iterate the entities from the bottom to the top
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.buffer.iter()
+    }
+    /// truncate the buffer down to the given length
+    pub fn rollback(&mut self, len: usize) -> Result<(), BufferErrorReason> {
+        if len <= self.buffer.len() {
+            self.buffer.truncate(len);
+            Ok(())
+        } else {
+            Err(BufferErrorReason::OutOfRangeAccess(self.buffer.len(), len))
+        }
+    }
+}
+impl<T: Clone> BufferMemory<T> {
+    /// append the given number of copies of an initial value
+    pub fn allocate(&mut self, num: usize, init: T) {
+        self.buffer.resize(self.buffer.len() + num, init);
+        self.max_seen = self.max_seen.max(self.buffer.len());
+    }
+}
+impl<T> Default for BufferMemory<T> {
+    fn default() -> Self {
+        BufferMemory::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop() {
+        let mut b = BufferMemory::new();
+        b.push(1);
+        b.push(2);
+        assert_eq!(b.here(), 2);
+        assert_eq!(b.pop().unwrap(), 2);
+        assert_eq!(b.pop().unwrap(), 1);
+        assert_eq!(b.pop().unwrap_err(), BufferErrorReason::OutOfRangeAccess(0, 0));
+    }
+
+    #[test]
+    fn test_pick_roll() {
+        let mut b = BufferMemory::new();
+        b.push(1);
+        b.push(2);
+        b.push(3);
+        assert_eq!(*b.pick(0).unwrap(), 3);
+        assert_eq!(*b.pick(2).unwrap(), 1);
+        assert_eq!(b.pick(3).unwrap_err(), BufferErrorReason::OutOfRangeAccess(3, 3));
+        b.roll(2).unwrap();
+        assert_eq!(*b.pick(0).unwrap(), 1);
+        assert_eq!(*b.pick(1).unwrap(), 3);
+        assert_eq!(*b.pick(2).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_peak() {
+        let mut b = BufferMemory::new();
+        for i in 0..5 {
+            b.push(i);
+        }
+        b.rollback(1).unwrap();
+        assert_eq!(b.here(), 1);
+        assert_eq!(b.peak(), 5);
+        b.allocate(6, 0);
+        assert_eq!(b.peak(), 7);
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut b = BufferMemory::new();
+        b.push(0);
+        b.reserve(1000);
+        assert_eq!(b.here(), 1);
+        for i in 1..1000 {
+            b.push(i);
+        }
+        assert_eq!(b.here(), 1000);
+        assert_eq!(*b.get(0).unwrap(), 0);
+        assert_eq!(*b.pick(0).unwrap(), 999);
+    }
+
+    #[test]
+    fn test_get_set() {
+        let mut b = BufferMemory::new();
+        b.allocate(3, 0);
+        b.set(1, 5).unwrap();
+        assert_eq!(*b.get(1).unwrap(), 5);
+        assert_eq!(b.get(3).unwrap_err(), BufferErrorReason::OutOfRangeAccess(3, 3));
+    }
+
+    #[test]
+    fn test_rollback_remove() {
+        let mut b = BufferMemory::new();
+        for i in 0..5 {
+            b.push(i);
+        }
+        b.remove(2).unwrap();
+        assert_eq!(b.here(), 3);
+        b.rollback(1).unwrap();
+        assert_eq!(b.here(), 1);
+        assert_eq!(b.rollback(2).unwrap_err(), BufferErrorReason::OutOfRangeAccess(1, 2));
+    }
+
+    #[test]
+    fn test_drain_top() {
+        let mut b = BufferMemory::new();
+        for i in 0..5 {
+            b.push(i);
+        }
+        assert_eq!(b.drain_top(3).unwrap(), vec![4, 3, 2]);
+        assert_eq!(b.here(), 2);
+        assert_eq!(b.drain_top(3).unwrap_err(), BufferErrorReason::OutOfRangeAccess(2, 3));
+    }
+
+    #[test]
+    fn test_error_display() {
+        let e = BufferErrorReason::OutOfRangeAccess(3, 7);
+        assert_eq!(format!("{}", e), "out of range: len=3, addr=7");
+        let e = BufferAddressErrorReason::InvalidAddress;
+        assert_eq!(format!("{}", e), "the address does not point at an entity");
+    }
+
+    #[test]
+    fn test_address() {
+        let a = CodeAddress(Address::Root);
+        assert_eq!(usize::try_from(a), Err(BufferAddressErrorReason::InvalidAddress));
+        let a = a.next();
+        assert_eq!(usize::try_from(a), Ok(0));
+        assert_eq!(usize::try_from(a.next()), Ok(1));
+    }
+}
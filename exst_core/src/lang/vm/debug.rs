@@ -0,0 +1,101 @@
+//! debug information
+//!
+//! While compiling, the VM records for every emitted instruction the
+//! source position of the token it came from. Dumps and error reports
+//! use this mapping to point back into the script.
+
+use super::buffer::CodeAddress;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// a position in a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodePosition {
+    /// name of the script
+    pub script_name: String,
+    /// line (1 origin)
+    pub line_number: usize,
+    /// column (1 origin)
+    pub column_number: usize,
+}
+impl CodePosition {
+    /// create a new position
+    pub fn new(script_name: String, line_number: usize, column_number: usize) -> Self {
+        CodePosition {
+            script_name,
+            line_number,
+            column_number,
+        }
+    }
+}
+
+/// mapping from code addresses to source positions
+#[derive(Debug)]
+pub struct DebugInfoStore {
+    code_mapping: HashMap<usize, CodePosition>,
+}
+impl DebugInfoStore {
+    /// create an empty store
+    pub fn new() -> Self {
+        DebugInfoStore {
+            code_mapping: HashMap::new(),
+        }
+    }
+    /// record the source position of an instruction
+    pub fn add(&mut self, code: CodeAddress, position: CodePosition) {
+        if let Ok(i) = usize::try_from(code) {
+            self.code_mapping.insert(i, position);
+        }
+    }
+    /// source position of an instruction, if recorded
+    pub fn get(&self, code: CodeAddress) -> Option<&CodePosition> {
+        usize::try_from(code)
+            .ok()
+            .and_then(|i| self.code_mapping.get(&i))
+    }
+    /// drop every mapping at or after the given address
+    pub fn remove_from(&mut self, code: CodeAddress) {
+        if let Ok(i) = usize::try_from(code) {
+            self.code_mapping.retain(|k, _| *k < i);
+        }
+    }
+}
+impl Default for DebugInfoStore {
+    fn default() -> Self {
+        DebugInfoStore::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_get() {
+        let mut s = DebugInfoStore::new();
+        s.add(
+            CodeAddress::from_index(3),
+            CodePosition::new(String::from("main"), 2, 5),
+        );
+        let p = s.get(CodeAddress::from_index(3)).unwrap();
+        assert_eq!(p.script_name, "main");
+        assert_eq!((p.line_number, p.column_number), (2, 5));
+        assert_eq!(s.get(CodeAddress::from_index(4)), None);
+    }
+
+    #[test]
+    fn test_remove_from() {
+        let mut s = DebugInfoStore::new();
+        s.add(
+            CodeAddress::from_index(1),
+            CodePosition::new(String::from("main"), 1, 1),
+        );
+        s.add(
+            CodeAddress::from_index(2),
+            CodePosition::new(String::from("main"), 1, 3),
+        );
+        s.remove_from(CodeAddress::from_index(2));
+        assert!(s.get(CodeAddress::from_index(1)).is_some());
+        assert!(s.get(CodeAddress::from_index(2)).is_none());
+    }
+}
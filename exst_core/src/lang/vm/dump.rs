@@ -0,0 +1,400 @@
+//! state dumps
+//!
+//! Helpers that render the state of a VM as text. Every function
+//! writes complete lines (without a trailing line feed) into the
+//! given sink, so callers decide where the text goes.
+
+use super::buffer::CodeAddress;
+use super::buffer::DataAddress;
+use super::value::Value;
+use super::Instruction;
+use super::Vm;
+use super::VmErrorReason;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// render one instruction with its address and debug position
+fn format_instruction_line<T, E>(
+    vm: &Vm<T, E>,
+    address: CodeAddress,
+    instruction: &Instruction<T, E>,
+) -> String
+where
+    T: fmt::Debug,
+    E: fmt::Debug,
+{
+    let mut line = format!(
+        "{:>6}: {:?}",
+        usize::try_from(address).map_or(String::from("root"), |i| i.to_string()),
+        instruction
+    );
+    // annotate jump and call targets with the word they point into
+    let target = match instruction {
+        Instruction::Call(target)
+        | Instruction::Jump(target)
+        | Instruction::Branch(target)
+        | Instruction::BranchZero(target)
+        | Instruction::LongJump(target) => Some(*target),
+        Instruction::LongJumpN(target, _) => Some(*target),
+        _ => None,
+    };
+    if let Some(target) = target {
+        if let Some((name, _)) = vm.word_dictionary().guess_name(target) {
+            line.push_str(&format!(" ({})", name));
+        }
+    }
+    if let Some(position) = vm.debug_info_store().get(address) {
+        line.push_str(&format!(
+            " # {}:{}:{}",
+            position.script_name, position.line_number, position.column_number
+        ));
+    }
+    line
+}
+
+/// dump the given number of instructions starting at an address
+pub fn dump_code<T, E>(
+    vm: &Vm<T, E>,
+    from: CodeAddress,
+    num: usize,
+    f: &mut dyn FnMut(&str),
+) where
+    T: fmt::Debug,
+    E: fmt::Debug,
+{
+    let mut address = from;
+    for _ in 0..num {
+        match vm.code_buffer().get(address) {
+            Ok(instruction) => f(&format_instruction_line(vm, address, instruction)),
+            Err(_) => break,
+        }
+        address = address.next();
+    }
+}
+
+/// dump the instructions between two addresses, ignoring word
+/// boundaries
+///
+/// The end address is exclusive and clamped to the code buffer
+/// length.
+pub fn dump_code_range<T, E>(
+    vm: &Vm<T, E>,
+    from: CodeAddress,
+    to: CodeAddress,
+    f: &mut dyn FnMut(&str),
+) where
+    T: fmt::Debug,
+    E: fmt::Debug,
+{
+    let from = match usize::try_from(from) {
+        Ok(i) => i,
+        Err(_) => return,
+    };
+    let to = usize::try_from(to)
+        .unwrap_or(0)
+        .min(vm.code_buffer().len());
+    for i in from..to {
+        let address = CodeAddress::from_index(i);
+        if let Ok(instruction) = vm.code_buffer().get(address) {
+            f(&format_instruction_line(vm, address, instruction));
+        }
+    }
+}
+
+/// dump the instructions of a named word
+pub fn dump_word_code<T, E>(
+    vm: &Vm<T, E>,
+    name: &str,
+    f: &mut dyn FnMut(&str),
+) -> Result<(), VmErrorReason<E>>
+where
+    T: fmt::Debug,
+    E: fmt::Debug,
+{
+    let word = vm
+        .word_dictionary()
+        .find_word(name)
+        .ok_or_else(|| VmErrorReason::UndefinedWord(String::from(name)))?;
+    let address = word.code();
+    // a primitive body is opaque; its documentation says more than
+    // the bare `CallPrimitive` instruction would
+    if let Ok(Instruction::CallPrimitive(_)) = vm.code_buffer().get(address) {
+        f(&format!("(primitive) {}", word.document()));
+        return Ok(());
+    }
+    for (address, instruction) in vm.code_buffer().word_instructions(address) {
+        f(&format_instruction_line(vm, address, instruction));
+    }
+    Ok(())
+}
+
+/// number of instructions of a named word, including its terminator
+pub fn word_instruction_count<T, E>(
+    vm: &Vm<T, E>,
+    name: &str,
+) -> Result<usize, VmErrorReason<E>> {
+    let word = vm
+        .word_dictionary()
+        .find_word(name)
+        .ok_or_else(|| VmErrorReason::UndefinedWord(String::from(name)))?;
+    let mut address = word.code();
+    let mut count = 0;
+    loop {
+        match vm.code_buffer().get(address) {
+            Err(_) => break,
+            Ok(Instruction::WordTerminator) => {
+                count += 1;
+                break;
+            }
+            Ok(_) => count += 1,
+        }
+        address = address.next();
+    }
+    Ok(count)
+}
+
+/// dump the source position of each instruction of a named word
+///
+/// Instructions without a recorded position print a `?`.
+pub fn dump_word_source<T, E>(
+    vm: &Vm<T, E>,
+    name: &str,
+    f: &mut dyn FnMut(&str),
+) -> Result<(), VmErrorReason<E>> {
+    let word = vm
+        .word_dictionary()
+        .find_word(name)
+        .ok_or_else(|| VmErrorReason::UndefinedWord(String::from(name)))?;
+    let mut address = word.code();
+    loop {
+        match vm.code_buffer().get(address) {
+            Err(_) | Ok(Instruction::WordTerminator) => break,
+            Ok(_) => {
+                let index =
+                    usize::try_from(address).map_or(String::from("root"), |i| i.to_string());
+                match vm.debug_info_store().get(address) {
+                    Some(position) => f(&format!(
+                        "{:>6}: {}:{}:{}",
+                        index, position.script_name, position.line_number, position.column_number
+                    )),
+                    None => f(&format!("{:>6}: ?", index)),
+                }
+            }
+        }
+        address = address.next();
+    }
+    Ok(())
+}
+
+/// dump the instructions of every defined word
+pub fn dump_all_word_code<T, E>(vm: &Vm<T, E>, f: &mut dyn FnMut(&str))
+where
+    T: fmt::Debug,
+    E: fmt::Debug,
+{
+    for (_, name) in vm.word_dictionary().all_word_names() {
+        f(&format!("{}:", name));
+        dump_word_code(vm, &name, f).ok();
+    }
+}
+
+/// name of a value's variant, used as the type column of dumps
+fn value_type_name<T>(v: &Value<T>) -> &'static str {
+    match v {
+        Value::IntValue(_) => "int",
+        Value::FloatValue(_) => "float",
+        Value::StrValue(_) => "str",
+        Value::CodeAddress(_) => "code",
+        Value::DataAddress(_) => "data",
+        Value::ExtValue(_) => "ext",
+    }
+}
+
+/// dump the given number of data buffer cells starting at an address
+///
+/// The dump stops cleanly at the end of the buffer.
+pub fn dump_data_buffer<T, E>(
+    vm: &Vm<T, E>,
+    from: DataAddress,
+    num: usize,
+    f: &mut dyn FnMut(&str),
+) where
+    T: fmt::Display,
+{
+    let from = match usize::try_from(from) {
+        Ok(i) => i,
+        Err(_) => return,
+    };
+    for i in from..from + num {
+        match vm.data_buffer().get(DataAddress::from_index(i)) {
+            Ok(v) => f(&format!("{:>6}: {:<6} {}", i, value_type_name(&v), v)),
+            Err(_) => break,
+        }
+    }
+}
+
+/// dump the data stack from the bottom to the top
+pub fn dump_data_stack<T, E>(vm: &Vm<T, E>, f: &mut dyn FnMut(&str))
+where
+    T: fmt::Display,
+{
+    for (i, v) in vm.data_stack().iter().enumerate() {
+        f(&format!("{:>6}: {}", i, v));
+    }
+}
+
+/// dump the environment stack from the bottom to the top
+pub fn dump_env_stack<T, E>(vm: &Vm<T, E>, f: &mut dyn FnMut(&str))
+where
+    T: fmt::Display,
+{
+    for (i, v) in vm.env_stack().iter().enumerate() {
+        f(&format!("{:>6}: {}", i, v));
+    }
+}
+
+/// dump the return stack from the bottom to the top
+pub fn dump_return_stack<T, E>(vm: &Vm<T, E>, f: &mut dyn FnMut(&str)) {
+    for (i, frame) in vm.return_stack().iter().enumerate() {
+        let name = vm
+            .word_dictionary()
+            .guess_name(frame.return_address())
+            .map_or(String::from("?"), |(name, _)| name.clone());
+        f(&format!(
+            "{:>6}: return to {:?} ({})",
+            i,
+            frame.return_address(),
+            name
+        ));
+    }
+}
+
+/// dump the interpreter state and the stack depths
+pub fn dump_vm_state<T, E>(vm: &Vm<T, E>, f: &mut dyn FnMut(&str)) {
+    f(&format!("state: {:?}", vm.state()));
+    f(&format!("execution state: {:?}", vm.exec_state()));
+    f(&format!("pc: {:?}", vm.pc()));
+    f(&format!("data stack depth: {}", vm.data_stack().here()));
+    f(&format!("return stack depth: {}", vm.return_stack().here()));
+    f(&format!("env stack depth: {}", vm.env_stack().here()));
+    f(&format!(
+        "controlflow stack depth: {}",
+        vm.controlflow_stack().here()
+    ));
+}
+
+/// dump everything about the machine
+pub fn dump_all_info<T, E>(vm: &Vm<T, E>, f: &mut dyn FnMut(&str))
+where
+    T: fmt::Debug + fmt::Display,
+    E: fmt::Debug,
+{
+    f("[vm state]");
+    dump_vm_state(vm, f);
+    f("[data stack]");
+    dump_data_stack(vm, f);
+    f("[env stack]");
+    dump_env_stack(vm, f);
+    f("[return stack]");
+    dump_return_stack(vm, f);
+    f("[words]");
+    for (_, name) in vm.word_dictionary().all_word_names() {
+        f(&name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::resource::BufferResources;
+    use crate::lang::vm::value::Value;
+    use std::rc::Rc;
+
+    type TestVm = Vm<i32, i32>;
+
+    #[test]
+    fn test_dump_word_code() {
+        let mut vm: TestVm = Vm::new(BufferResources::new());
+        vm.define_word_with_instructions(
+            "w",
+            false,
+            "",
+            vec![Instruction::Push(Rc::new(Value::IntValue(1)))],
+        );
+        let mut lines = Vec::new();
+        dump_word_code(&vm, "w", &mut |s| lines.push(String::from(s))).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("Push"));
+        assert!(lines[1].contains("Return"));
+        match dump_word_code(&vm, "missing", &mut |_| {}) {
+            Err(VmErrorReason::UndefinedWord(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_dump_primitive_word() {
+        let mut vm: TestVm = Vm::new(BufferResources::new());
+        vm.define_primitive_word("p", false, "a b -- c : sum", |_| Ok(()));
+        let mut lines = Vec::new();
+        dump_word_code(&vm, "p", &mut |s| lines.push(String::from(s))).unwrap();
+        assert_eq!(lines, [String::from("(primitive) a b -- c : sum")]);
+    }
+
+    #[test]
+    fn test_dump_resolves_call_targets() {
+        let mut vm: TestVm = Vm::new(BufferResources::new());
+        vm.define_word_with_instructions(
+            "callee",
+            false,
+            "",
+            vec![Instruction::Push(Rc::new(Value::IntValue(1)))],
+        );
+        let code = vm.word_dictionary().find_word("callee").unwrap().code();
+        vm.define_word_with_instructions("caller", false, "", vec![Instruction::Call(code)]);
+        let mut lines = Vec::new();
+        dump_word_code(&vm, "caller", &mut |s| lines.push(String::from(s))).unwrap();
+        assert!(lines[0].contains("(callee)"));
+    }
+
+    #[test]
+    fn test_dump_code_range() {
+        let mut vm: TestVm = Vm::new(BufferResources::new());
+        vm.define_word_with_instructions(
+            "w",
+            false,
+            "",
+            vec![
+                Instruction::Push(Rc::new(Value::IntValue(1))),
+                Instruction::Push(Rc::new(Value::IntValue(2))),
+            ],
+        );
+        let mut lines = Vec::new();
+        dump_code_range(
+            &vm,
+            CodeAddress::from_index(0),
+            CodeAddress::from_index(2),
+            &mut |s| lines.push(String::from(s)),
+        );
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("Push"));
+        assert!(lines[1].contains("Push"));
+        // the end address is clamped to the buffer length
+        lines.clear();
+        dump_code_range(
+            &vm,
+            CodeAddress::from_index(0),
+            CodeAddress::from_index(100),
+            &mut |s| lines.push(String::from(s)),
+        );
+        assert_eq!(lines.len(), vm.code_buffer().len());
+    }
+
+    #[test]
+    fn test_dump_vm_state() {
+        let vm: TestVm = Vm::new(BufferResources::new());
+        let mut lines = Vec::new();
+        dump_vm_state(&vm, &mut |s| lines.push(String::from(s)));
+        assert!(lines.iter().any(|l| l.contains("data stack depth: 0")));
+    }
+}
@@ -0,0 +1,1846 @@
+//! virtual machine
+//!
+//! The VM is a token driven machine. The outer loop reads tokens from
+//! the current input stream and either executes them immediately
+//! (interpretation state) or appends instructions to the code buffer
+//! (compilation state). Executing a word pushes a call frame and runs
+//! the inner instruction loop until the frame is popped again.
+//!
+//! The machine is generic over `T`, an embedder defined extra value
+//! type, and `E`, an embedder defined extra error type.
+
+pub mod buffer;
+pub mod value;
+pub mod word;
+pub mod debug;
+pub mod dump;
+
+use super::resource::ResourceErrorReason;
+use super::resource::Resources;
+use super::tokenizer::EmptyTokenStream;
+use super::tokenizer::Token;
+use super::tokenizer::TokenIterator;
+use super::tokenizer::TokenizerError;
+use super::tokenizer::TokenWithComment;
+use super::tokenizer::ValueToken;
+use buffer::Address;
+use buffer::BufferAddressErrorReason;
+use buffer::BufferErrorReason;
+use buffer::BufferMemory;
+use buffer::CodeAddress;
+use buffer::DataAddress;
+use debug::CodePosition;
+use debug::DebugInfoStore;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use value::Value;
+use word::Dictionary;
+use word::LocalDictionary;
+use word::Word;
+
+/// reason of a trap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapReason {
+    /// a trap requested by the script
+    UserTrap,
+    /// a trap requested by the script, carrying a user-supplied code
+    UserTrapCode(i32),
+}
+
+/// reason of a data stack error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataStackErrorReason {
+    /// the underlying buffer access failed
+    BufferAccessError(BufferErrorReason),
+    /// `pick` reached below the bottom; carries the depth and the
+    /// requested position
+    PickOutOfRange(usize, usize),
+    /// `roll` reached below the bottom; carries the depth and the
+    /// requested position
+    RollOutOfRange(usize, usize),
+}
+impl From<BufferErrorReason> for DataStackErrorReason {
+    fn from(e: BufferErrorReason) -> Self {
+        DataStackErrorReason::BufferAccessError(e)
+    }
+}
+
+/// reason of a VM error
+#[derive(Debug, PartialEq)]
+pub enum VmErrorReason<E> {
+    /// a value had the wrong type; carries the expected type
+    TypeMismatchError(&'static str),
+    /// integer division by zero
+    ZeroDivisionError,
+    /// no word is defined under the given name
+    UndefinedWord(String),
+    /// the word machinery was used inconsistently
+    WordError(&'static str),
+    /// data stack access failed
+    DataStackAccessError(DataStackErrorReason),
+    /// environment stack access failed
+    EnvStackAccessError(BufferErrorReason),
+    /// scratch stack access failed
+    ScratchStackAccessError(BufferErrorReason),
+    /// return stack access failed
+    ReturnStackAccessError(BufferErrorReason),
+    /// control flow stack access failed
+    ControlflowStackAccessError(BufferErrorReason),
+    /// code buffer access failed
+    CodeBufferAccessError(BufferErrorReason),
+    /// data buffer access failed
+    DataBufferAccessError(BufferErrorReason),
+    /// an address could not be converted
+    AddressError(BufferAddressErrorReason),
+    /// the tokenizer failed
+    TokenizerError(TokenizerError),
+    /// a resource could not be loaded
+    ResourceError(ResourceErrorReason),
+    /// the script call stack grew past the configured limit
+    ScriptCallDepthExceeded(usize),
+    /// the configured instruction budget ran out
+    InstructionBudgetExceeded,
+    /// the data stack grew past the configured limit
+    DataStackLimitExceeded(usize),
+    /// the script trapped
+    TrapError(TrapReason),
+    /// embedder defined error
+    ExtraError(E),
+}
+impl<E: std::fmt::Debug> std::fmt::Display for VmErrorReason<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VmErrorReason::TypeMismatchError(expected) => {
+                write!(f, "type mismatch: expected {}", expected)
+            }
+            VmErrorReason::ZeroDivisionError => write!(f, "division by zero"),
+            VmErrorReason::UndefinedWord(name) => write!(f, "undefined word: {}", name),
+            VmErrorReason::WordError(message) => write!(f, "{}", message),
+            VmErrorReason::DataStackAccessError(e) => write!(f, "data stack error: {:?}", e),
+            VmErrorReason::EnvStackAccessError(e) => write!(f, "env stack error: {}", e),
+            VmErrorReason::ScratchStackAccessError(e) => {
+                write!(f, "scratch stack error: {}", e)
+            }
+            VmErrorReason::ReturnStackAccessError(e) => write!(f, "return stack error: {}", e),
+            VmErrorReason::ControlflowStackAccessError(e) => {
+                write!(f, "control flow stack error: {}", e)
+            }
+            VmErrorReason::CodeBufferAccessError(e) => write!(f, "code buffer error: {}", e),
+            VmErrorReason::DataBufferAccessError(e) => write!(f, "data buffer error: {}", e),
+            VmErrorReason::AddressError(e) => write!(f, "address error: {}", e),
+            VmErrorReason::TokenizerError(e) => {
+                if let Some(name) = e.script_name() {
+                    write!(f, "tokenize error in {} ", name)?;
+                } else {
+                    write!(f, "tokenize error ")?;
+                }
+                write!(
+                    f,
+                    "at line {}, column {}: {:?}",
+                    e.line_number(),
+                    e.column_number(),
+                    e.reason()
+                )
+            }
+            VmErrorReason::ResourceError(e) => write!(f, "resource error: {:?}", e),
+            VmErrorReason::ScriptCallDepthExceeded(max) => {
+                write!(f, "script call depth exceeded the limit of {}", max)
+            }
+            VmErrorReason::InstructionBudgetExceeded => {
+                write!(f, "instruction budget exceeded")
+            }
+            VmErrorReason::DataStackLimitExceeded(limit) => {
+                write!(f, "data stack grew past the limit of {}", limit)
+            }
+            VmErrorReason::TrapError(reason) => write!(f, "trap: {:?}", reason),
+            VmErrorReason::ExtraError(e) => write!(f, "{:?}", e),
+        }
+    }
+}
+impl<E> VmErrorReason<E> {
+    /// whether execution can continue with another `exec` call
+    ///
+    /// A trap advances the program counter past the trapping
+    /// instruction before reporting, so once the caller fixed the
+    /// state the trap complained about, `exec` picks up right after
+    /// it with the env and return stacks intact.
+    pub fn is_resumable(&self) -> bool {
+        matches!(self, VmErrorReason::TrapError(_))
+    }
+}
+impl<E> From<TokenizerError> for VmErrorReason<E> {
+    fn from(e: TokenizerError) -> Self {
+        VmErrorReason::TokenizerError(e)
+    }
+}
+impl<E> From<ResourceErrorReason> for VmErrorReason<E> {
+    fn from(e: ResourceErrorReason) -> Self {
+        VmErrorReason::ResourceError(e)
+    }
+}
+impl<E> From<DataStackErrorReason> for VmErrorReason<E> {
+    fn from(e: DataStackErrorReason) -> Self {
+        VmErrorReason::DataStackAccessError(e)
+    }
+}
+impl<E> From<BufferAddressErrorReason> for VmErrorReason<E> {
+    fn from(e: BufferAddressErrorReason) -> Self {
+        VmErrorReason::AddressError(e)
+    }
+}
+
+/// state of the outer interpreter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmState {
+    /// tokens are executed immediately
+    Interpretation,
+    /// tokens are compiled into the reserved word
+    Compilation,
+    /// like `Compilation`, but the reserved word may call itself
+    RecursableCompilation,
+}
+
+/// how declared stack effects are verified at the end of a definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackCheckMode {
+    /// definitions are never checked
+    Off,
+    /// an impossible effect is reported on stderr
+    Warn,
+    /// an impossible effect fails the definition
+    Error,
+}
+
+/// how redefining an already defined word is reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedefinitionCheckMode {
+    /// redefinitions are silent
+    Off,
+    /// a redefinition is reported on stderr
+    Warn,
+    /// a redefinition fails the definition
+    Error,
+}
+
+/// execution state of the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmExecutionState {
+    /// the machine is running
+    NormalState,
+    /// the machine stops at the next opportunity
+    FinishState,
+}
+
+/// signature of a primitive word implementation
+pub type PrimitiveWordFunction<T, E> = fn(&mut Vm<T, E>) -> Result<(), VmErrorReason<E>>;
+
+/// one row of a primitive word table: name, immediate flag,
+/// documentation and implementation
+pub type PrimitiveWordDef<'a, T, E> = (&'a str, bool, &'a str, PrimitiveWordFunction<T, E>);
+
+/// signature of the handler consulted for unknown symbols
+pub type UnknownSymbolHandler<T, E> = Box<dyn FnMut(&str) -> Option<Instruction<T, E>>>;
+
+/// one instruction of the code buffer
+#[derive(Debug)]
+pub enum Instruction<T, E> {
+    /// push a value on the data stack
+    Push(Rc<Value<T>>),
+    /// call the word starting at the given address
+    Call(CodeAddress),
+    /// call a primitive word implementation
+    CallPrimitive(PrimitiveWordFunction<T, E>),
+    /// jump to the given address
+    Jump(CodeAddress),
+    /// pop a value and jump to the given address when it is true
+    Branch(CodeAddress),
+    /// pop a value and jump to the given address when it is false
+    BranchZero(CodeAddress),
+    /// pop an execution token and call it
+    Exec,
+    /// pop the current call frame and return to the caller
+    Return,
+    /// marks the end of a word; never executed
+    WordTerminator,
+    /// pop a value from the data stack into the environment stack
+    LocalDef,
+    /// push a copy of the local with the given index
+    LocalRef(usize),
+    /// abort execution with the given reason; execution can be
+    /// resumed at the following instruction
+    Trap(TrapReason),
+    /// pop a value, unwind the data stack to the current frame base,
+    /// push the value back and jump to the given address
+    LongJump(CodeAddress),
+    /// like `LongJump`, but preserve the given number of top values
+    /// across the unwind, in their original order
+    LongJumpN(CodeAddress, usize),
+    /// pop a value from the data stack onto the scratch stack
+    ScratchPush,
+    /// pop a value from the scratch stack onto the data stack
+    ScratchPop,
+    /// push a copy of the scratch stack top onto the data stack
+    ScratchCopy,
+}
+impl<T, E> Clone for Instruction<T, E> {
+    fn clone(&self) -> Self {
+        match self {
+            Instruction::Push(v) => Instruction::Push(Rc::clone(v)),
+            Instruction::Call(a) => Instruction::Call(*a),
+            Instruction::CallPrimitive(f) => Instruction::CallPrimitive(*f),
+            Instruction::Jump(a) => Instruction::Jump(*a),
+            Instruction::Branch(a) => Instruction::Branch(*a),
+            Instruction::BranchZero(a) => Instruction::BranchZero(*a),
+            Instruction::Exec => Instruction::Exec,
+            Instruction::Return => Instruction::Return,
+            Instruction::WordTerminator => Instruction::WordTerminator,
+            Instruction::LocalDef => Instruction::LocalDef,
+            Instruction::LocalRef(i) => Instruction::LocalRef(*i),
+            Instruction::Trap(r) => Instruction::Trap(*r),
+            Instruction::LongJump(a) => Instruction::LongJump(*a),
+            Instruction::LongJumpN(a, n) => Instruction::LongJumpN(*a, *n),
+            Instruction::ScratchPush => Instruction::ScratchPush,
+            Instruction::ScratchPop => Instruction::ScratchPop,
+            Instruction::ScratchCopy => Instruction::ScratchCopy,
+        }
+    }
+}
+
+/// one frame of the return stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    return_address: CodeAddress,
+    env_base: usize,
+    data_base: usize,
+    scratch_base: usize,
+}
+impl CallFrame {
+    /// create a new frame
+    pub fn new(
+        return_address: CodeAddress,
+        env_base: usize,
+        data_base: usize,
+        scratch_base: usize,
+    ) -> Self {
+        CallFrame {
+            return_address,
+            env_base,
+            data_base,
+            scratch_base,
+        }
+    }
+    /// where execution continues after the call
+    pub fn return_address(&self) -> CodeAddress {
+        self.return_address
+    }
+    /// environment stack depth when the call was made
+    pub fn env_base(&self) -> usize {
+        self.env_base
+    }
+    /// data stack depth when the call was made
+    pub fn data_base(&self) -> usize {
+        self.data_base
+    }
+    /// scratch stack depth when the call was made
+    pub fn scratch_base(&self) -> usize {
+        self.scratch_base
+    }
+}
+
+/// the data stack
+#[derive(Debug)]
+pub struct DataStack<T> {
+    buffer: BufferMemory<Rc<Value<T>>>,
+}
+impl<T> DataStack<T> {
+    /// create an empty stack
+    pub fn new() -> Self {
+        DataStack {
+            buffer: BufferMemory::new(),
+        }
+    }
+    /// number of values on the stack
+    pub fn here(&self) -> usize {
+        self.buffer.here()
+    }
+    /// largest number of values the stack ever held
+    pub fn peak(&self) -> usize {
+        self.buffer.peak()
+    }
+    /// push a value
+    pub fn push(&mut self, v: Rc<Value<T>>) {
+        self.buffer.push(v);
+    }
+    /// pop the top value
+    pub fn pop(&mut self) -> Result<Rc<Value<T>>, DataStackErrorReason> {
+        Ok(self.buffer.pop()?)
+    }
+    /// copy of the value at the given position from the top (0 is the top)
+    pub fn pick(&self, position: usize) -> Result<Rc<Value<T>>, DataStackErrorReason> {
+        match self.buffer.pick(position) {
+            Ok(v) => Ok(Rc::clone(v)),
+            Err(BufferErrorReason::OutOfRangeAccess(len, pos)) => {
+                Err(DataStackErrorReason::PickOutOfRange(len, pos))
+            }
+        }
+    }
+    /// move the value at the given position from the top to the top
+    pub fn roll(&mut self, position: usize) -> Result<(), DataStackErrorReason> {
+        match self.buffer.roll(position) {
+            Ok(()) => Ok(()),
+            Err(BufferErrorReason::OutOfRangeAccess(len, pos)) => {
+                Err(DataStackErrorReason::RollOutOfRange(len, pos))
+            }
+        }
+    }
+    /// truncate the stack down to the given depth
+    pub fn rollback(&mut self, len: usize) -> Result<(), DataStackErrorReason> {
+        Ok(self.buffer.rollback(len)?)
+    }
+    /// remove the given number of values from the top, top first
+    pub fn drain_top(&mut self, num: usize) -> Result<Vec<Rc<Value<T>>>, DataStackErrorReason> {
+        Ok(self.buffer.drain_top(num)?)
+    }
+    /// remove every value from the stack
+    pub fn clear(&mut self) {
+        self.buffer.rollback(0).ok();
+    }
+    /// copy of the whole stack contents, bottom first
+    ///
+    /// Cheap because values are shared through `Rc`.
+    pub fn snapshot(&self) -> Vec<Rc<Value<T>>> {
+        self.buffer.iter().map(Rc::clone).collect()
+    }
+    /// replace the stack contents with a previous snapshot
+    pub fn restore(&mut self, snapshot: Vec<Rc<Value<T>>>) {
+        self.buffer.rollback(0).ok();
+        for v in snapshot {
+            self.buffer.push(v);
+        }
+    }
+    /// iterate the values from the bottom to the top
+    pub fn iter(&self) -> std::slice::Iter<'_, Rc<Value<T>>> {
+        self.buffer.iter()
+    }
+}
+impl<T> Default for DataStack<T> {
+    fn default() -> Self {
+        DataStack::new()
+    }
+}
+
+/// the code buffer
+#[derive(Debug)]
+pub struct CodeBuffer<T, E> {
+    buffer: BufferMemory<Instruction<T, E>>,
+}
+impl<T, E> CodeBuffer<T, E> {
+    /// create an empty buffer
+    pub fn new() -> Self {
+        CodeBuffer {
+            buffer: BufferMemory::new(),
+        }
+    }
+    /// address of the next instruction to be appended
+    pub fn here(&self) -> CodeAddress {
+        CodeAddress::from_index(self.buffer.here())
+    }
+    /// number of instructions in the buffer
+    pub fn len(&self) -> usize {
+        self.buffer.here()
+    }
+    /// true if the buffer holds no instructions
+    pub fn is_empty(&self) -> bool {
+        self.buffer.here() == 0
+    }
+    /// pre-size the buffer for at least the given number of
+    /// additional instructions
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.reserve(additional);
+    }
+    /// append an instruction
+    pub fn push(&mut self, instruction: Instruction<T, E>) {
+        self.buffer.push(instruction);
+    }
+    /// instruction at the given address
+    pub fn get(&self, address: CodeAddress) -> Result<&Instruction<T, E>, BufferErrorReason> {
+        match usize::try_from(address) {
+            Ok(i) => self.buffer.get(i),
+            Err(_) => Err(BufferErrorReason::OutOfRangeAccess(
+                self.buffer.here(),
+                usize::MAX,
+            )),
+        }
+    }
+    /// overwrite the instruction at the given address
+    pub fn set(
+        &mut self,
+        address: CodeAddress,
+        instruction: Instruction<T, E>,
+    ) -> Result<(), BufferErrorReason> {
+        match usize::try_from(address) {
+            Ok(i) => self.buffer.set(i, instruction),
+            Err(_) => Err(BufferErrorReason::OutOfRangeAccess(
+                self.buffer.here(),
+                usize::MAX,
+            )),
+        }
+    }
+    /// truncate the buffer down to the given length
+    pub fn rollback(&mut self, len: usize) -> Result<(), BufferErrorReason> {
+        self.buffer.rollback(len)
+    }
+    /// iterate the instructions of the word starting at the given
+    /// address, together with their addresses
+    ///
+    /// The iteration stops before the `WordTerminator`, or at the
+    /// end of the buffer for an unterminated body. This is the
+    /// reusable core behind `dump_word_code` and analysis passes.
+    pub fn word_instructions(
+        &self,
+        start: CodeAddress,
+    ) -> impl Iterator<Item = (CodeAddress, &Instruction<T, E>)> + '_ {
+        let mut address = start;
+        std::iter::from_fn(move || match self.get(address) {
+            Err(_) | Ok(Instruction::WordTerminator) => None,
+            Ok(instruction) => {
+                let current = address;
+                address = address.next();
+                Some((current, instruction))
+            }
+        })
+    }
+}
+impl<T, E> Default for CodeBuffer<T, E> {
+    fn default() -> Self {
+        CodeBuffer::new()
+    }
+}
+
+/// the data buffer
+#[derive(Debug)]
+pub struct DataBuffer<T> {
+    buffer: BufferMemory<Rc<Value<T>>>,
+}
+impl<T> DataBuffer<T> {
+    /// create an empty buffer
+    pub fn new() -> Self {
+        DataBuffer {
+            buffer: BufferMemory::new(),
+        }
+    }
+    /// address of the next cell to be appended
+    pub fn here(&self) -> DataAddress {
+        DataAddress::from_index(self.buffer.here())
+    }
+    /// number of cells in the buffer
+    pub fn len(&self) -> usize {
+        self.buffer.here()
+    }
+    /// true if the buffer holds no cells
+    pub fn is_empty(&self) -> bool {
+        self.buffer.here() == 0
+    }
+    /// pre-size the buffer for at least the given number of
+    /// additional cells
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.reserve(additional);
+    }
+    /// append a cell
+    pub fn push(&mut self, v: Rc<Value<T>>) {
+        self.buffer.push(v);
+    }
+    /// append the given number of cells, initialized to the integer zero
+    pub fn allocate(&mut self, num: usize) {
+        self.buffer.allocate(num, Rc::new(Value::IntValue(0)));
+    }
+    /// copy of the cell at the given address
+    pub fn get(&self, address: DataAddress) -> Result<Rc<Value<T>>, BufferErrorReason> {
+        match usize::try_from(address) {
+            Ok(i) => Ok(Rc::clone(self.buffer.get(i)?)),
+            Err(_) => Err(BufferErrorReason::OutOfRangeAccess(
+                self.buffer.here(),
+                usize::MAX,
+            )),
+        }
+    }
+    /// overwrite the cell at the given address
+    pub fn set(&mut self, address: DataAddress, v: Rc<Value<T>>) -> Result<(), BufferErrorReason> {
+        match usize::try_from(address) {
+            Ok(i) => self.buffer.set(i, v),
+            Err(_) => Err(BufferErrorReason::OutOfRangeAccess(
+                self.buffer.here(),
+                usize::MAX,
+            )),
+        }
+    }
+    /// truncate the buffer down to the given length
+    pub fn rollback(&mut self, len: usize) -> Result<(), BufferErrorReason> {
+        self.buffer.rollback(len)
+    }
+}
+impl<T> Default for DataBuffer<T> {
+    fn default() -> Self {
+        DataBuffer::new()
+    }
+}
+
+/// the virtual machine
+pub struct Vm<T, E> {
+    code_buffer: CodeBuffer<T, E>,
+    data_buffer: DataBuffer<T>,
+    data_stack: DataStack<T>,
+    return_stack: BufferMemory<CallFrame>,
+    scratch_stack: BufferMemory<Rc<Value<T>>>,
+    env_stack: BufferMemory<Rc<Value<T>>>,
+    controlflow_stack: BufferMemory<CodeAddress>,
+    word_dictionary: Dictionary,
+    local_dictionary: LocalDictionary,
+    debug_info_store: DebugInfoStore,
+    resources: Box<dyn Resources>,
+    input_stream: Box<dyn TokenIterator>,
+    script_call_stack: Vec<Box<dyn TokenIterator>>,
+    state: VmState,
+    exec_state: VmExecutionState,
+    pc: CodeAddress,
+    anonymous_definition: bool,
+    current_position: CodePosition,
+    string_interner: Option<std::collections::HashMap<String, Rc<Value<T>>>>,
+    typed_cells: std::collections::HashMap<DataAddress, i32>,
+    stack_snapshots: Vec<Vec<Rc<Value<T>>>>,
+    stack_check_mode: StackCheckMode,
+    redefinition_check_mode: RedefinitionCheckMode,
+    unknown_symbol_handler: Option<UnknownSymbolHandler<T, E>>,
+    include_chain: Vec<String>,
+    max_script_depth: Option<usize>,
+    instruction_budget: Option<u64>,
+    data_stack_limit: Option<usize>,
+    strict_compare: bool,
+    auto_doc_comment: bool,
+    last_comment: Option<String>,
+    last_comment_fresh: bool,
+}
+impl<T, E> Vm<T, E> {
+    /// create a new machine
+    ///
+    /// The machine starts with an empty input stream; load a script
+    /// with `call_script` before calling `exec`.
+    ///
+    /// # Arguments
+    /// * `resources` - resource implementation used by the machine
+    pub fn new<R: Resources + 'static>(resources: R) -> Self {
+        let input_stream = EmptyTokenStream::with_name(String::from("<init>"));
+        let current_position =
+            CodePosition::new(String::from(input_stream.script_name()), 0, 0);
+        Vm {
+            code_buffer: CodeBuffer::new(),
+            data_buffer: DataBuffer::new(),
+            data_stack: DataStack::new(),
+            return_stack: BufferMemory::new(),
+            scratch_stack: BufferMemory::new(),
+            env_stack: BufferMemory::new(),
+            controlflow_stack: BufferMemory::new(),
+            word_dictionary: Dictionary::new(),
+            local_dictionary: LocalDictionary::new(),
+            debug_info_store: DebugInfoStore::new(),
+            resources: Box::new(resources),
+            input_stream: Box::new(input_stream),
+            script_call_stack: Vec::new(),
+            state: VmState::Interpretation,
+            exec_state: VmExecutionState::NormalState,
+            pc: CodeAddress(Address::Root),
+            anonymous_definition: false,
+            current_position,
+            string_interner: Some(std::collections::HashMap::new()),
+            typed_cells: std::collections::HashMap::new(),
+            stack_snapshots: Vec::new(),
+            unknown_symbol_handler: None,
+            include_chain: Vec::new(),
+            max_script_depth: None,
+            instruction_budget: None,
+            data_stack_limit: None,
+            strict_compare: false,
+            auto_doc_comment: false,
+            last_comment: None,
+            last_comment_fresh: false,
+            stack_check_mode: StackCheckMode::Off,
+            redefinition_check_mode: RedefinitionCheckMode::Off,
+        }
+    }
+
+    /// turn string literal interning on or off
+    ///
+    /// With interning on (the default), identical string literals
+    /// pushed or compiled by the token loop share one allocation.
+    pub fn set_interning_enabled(&mut self, enabled: bool) {
+        if enabled {
+            if self.string_interner.is_none() {
+                self.string_interner = Some(std::collections::HashMap::new());
+            }
+        } else {
+            self.string_interner = None;
+        }
+    }
+
+    /// a shared string value, taken from the interner when enabled
+    fn intern_string(&mut self, v: String) -> Rc<Value<T>> {
+        match self.string_interner.as_mut() {
+            Some(interner) => Rc::clone(
+                interner
+                    .entry(v.clone())
+                    .or_insert_with(|| Rc::new(Value::StrValue(v))),
+            ),
+            None => Rc::new(Value::StrValue(v)),
+        }
+    }
+
+    /// restrict the data buffer cell at the given address to values
+    /// of the given type tag
+    pub fn register_typed_cell(&mut self, address: DataAddress, type_id: i32) {
+        self.typed_cells.insert(address, type_id);
+    }
+
+    /// expected type tag of a data buffer cell, if one was registered
+    pub fn typed_cell_type(&self, address: DataAddress) -> Option<i32> {
+        self.typed_cells.get(&address).copied()
+    }
+
+    /// truncate the data buffer down to the given length
+    ///
+    /// Typed cell registrations at or above the new end are dropped
+    /// with their cells, so a later allocation reusing one of the
+    /// addresses does not inherit a stale type constraint.
+    pub fn rollback_data_buffer(&mut self, len: usize) -> Result<(), VmErrorReason<E>> {
+        self.data_buffer
+            .rollback(len)
+            .map_err(VmErrorReason::DataBufferAccessError)?;
+        self.typed_cells
+            .retain(|address, _| matches!(address.0, Address::Entity(i) if i < len));
+        Ok(())
+    }
+
+    /// how declared stack effects are verified at the end of a definition
+    pub fn stack_check_mode(&self) -> StackCheckMode {
+        self.stack_check_mode
+    }
+
+    /// turn stack effect checking off, or have it warn or error
+    pub fn set_stack_check_mode(&mut self, mode: StackCheckMode) {
+        self.stack_check_mode = mode;
+    }
+
+    /// how redefining an existing word is reported
+    pub fn redefinition_check_mode(&self) -> RedefinitionCheckMode {
+        self.redefinition_check_mode
+    }
+
+    /// change how redefining an existing word is reported
+    pub fn set_redefinition_check_mode(&mut self, mode: RedefinitionCheckMode) {
+        self.redefinition_check_mode = mode;
+    }
+
+    /// install a handler consulted when a compiled symbol is not
+    /// found in the local or word dictionaries
+    ///
+    /// When the handler returns an instruction it is compiled in
+    /// place of the `UndefinedWord` error, so embedders can resolve
+    /// dynamic names (for example auto-creating variables).
+    pub fn set_unknown_symbol_handler(&mut self, handler: UnknownSymbolHandler<T, E>) {
+        self.unknown_symbol_handler = Some(handler);
+    }
+
+    /// save a snapshot of the data stack, returning its handle
+    pub fn take_stack_snapshot(&mut self) -> usize {
+        self.stack_snapshots.push(self.data_stack.snapshot());
+        self.stack_snapshots.len() - 1
+    }
+
+    /// restore the data stack from a snapshot handle
+    ///
+    /// The handle and every handle taken after it are consumed.
+    /// Returns false when the handle is unknown.
+    pub fn restore_stack_snapshot(&mut self, handle: usize) -> bool {
+        if handle >= self.stack_snapshots.len() {
+            return false;
+        }
+        self.stack_snapshots.truncate(handle + 1);
+        if let Some(snapshot) = self.stack_snapshots.pop() {
+            self.data_stack.restore(snapshot);
+        }
+        true
+    }
+
+    /// the code buffer
+    pub fn code_buffer(&self) -> &CodeBuffer<T, E> {
+        &self.code_buffer
+    }
+    /// the code buffer
+    pub fn code_buffer_mut(&mut self) -> &mut CodeBuffer<T, E> {
+        &mut self.code_buffer
+    }
+    /// the data buffer
+    pub fn data_buffer(&self) -> &DataBuffer<T> {
+        &self.data_buffer
+    }
+    /// the data buffer
+    pub fn data_buffer_mut(&mut self) -> &mut DataBuffer<T> {
+        &mut self.data_buffer
+    }
+    /// the data stack
+    pub fn data_stack(&self) -> &DataStack<T> {
+        &self.data_stack
+    }
+    /// the data stack
+    pub fn data_stack_mut(&mut self) -> &mut DataStack<T> {
+        &mut self.data_stack
+    }
+    /// the return stack
+    pub fn return_stack(&self) -> &BufferMemory<CallFrame> {
+        &self.return_stack
+    }
+    /// the scratch stack used by the return stack words
+    pub fn scratch_stack(&self) -> &BufferMemory<Rc<Value<T>>> {
+        &self.scratch_stack
+    }
+    /// the scratch stack used by the return stack words
+    pub fn scratch_stack_mut(&mut self) -> &mut BufferMemory<Rc<Value<T>>> {
+        &mut self.scratch_stack
+    }
+    /// the environment stack
+    pub fn env_stack(&self) -> &BufferMemory<Rc<Value<T>>> {
+        &self.env_stack
+    }
+    /// the environment stack
+    pub fn env_stack_mut(&mut self) -> &mut BufferMemory<Rc<Value<T>>> {
+        &mut self.env_stack
+    }
+    /// the control flow stack
+    pub fn controlflow_stack(&self) -> &BufferMemory<CodeAddress> {
+        &self.controlflow_stack
+    }
+    /// the control flow stack
+    pub fn controlflow_stack_mut(&mut self) -> &mut BufferMemory<CodeAddress> {
+        &mut self.controlflow_stack
+    }
+    /// the word dictionary
+    pub fn word_dictionary(&self) -> &Dictionary {
+        &self.word_dictionary
+    }
+    /// the word dictionary
+    pub fn word_dictionary_mut(&mut self) -> &mut Dictionary {
+        &mut self.word_dictionary
+    }
+    /// the local dictionary of the definition being compiled
+    pub fn local_dictionary(&self) -> &LocalDictionary {
+        &self.local_dictionary
+    }
+    /// the local dictionary of the definition being compiled
+    pub fn local_dictionary_mut(&mut self) -> &mut LocalDictionary {
+        &mut self.local_dictionary
+    }
+    /// the debug information store
+    pub fn debug_info_store(&self) -> &DebugInfoStore {
+        &self.debug_info_store
+    }
+    /// the debug information store
+    pub fn debug_info_store_mut(&mut self) -> &mut DebugInfoStore {
+        &mut self.debug_info_store
+    }
+    /// the resource implementation
+    pub fn resources(&self) -> &dyn Resources {
+        self.resources.as_ref()
+    }
+    /// the current input stream
+    pub fn input_stream_mut(&mut self) -> &mut dyn TokenIterator {
+        self.input_stream.as_mut()
+    }
+    /// state of the outer interpreter
+    pub fn state(&self) -> VmState {
+        self.state
+    }
+    /// change the state of the outer interpreter
+    pub fn set_state(&mut self, state: VmState) {
+        self.state = state;
+    }
+    /// execution state of the machine
+    pub fn exec_state(&self) -> VmExecutionState {
+        self.exec_state
+    }
+    /// change the execution state of the machine
+    pub fn set_exec_state(&mut self, exec_state: VmExecutionState) {
+        self.exec_state = exec_state;
+    }
+    /// the program counter
+    pub fn pc(&self) -> CodeAddress {
+        self.pc
+    }
+    /// source position of the token being handled
+    pub fn current_position(&self) -> &CodePosition {
+        &self.current_position
+    }
+
+    /// define a primitive word, returning its code address
+    ///
+    /// # Arguments
+    /// * `name` - name of the word
+    /// * `immediate` - true if the word executes during compilation
+    /// * `document` - documentation of the word
+    /// * `function` - implementation of the word
+    pub fn define_primitive_word(
+        &mut self,
+        name: &str,
+        immediate: bool,
+        document: &str,
+        function: PrimitiveWordFunction<T, E>,
+    ) -> CodeAddress {
+        self.define_word_with_instructions(
+            name,
+            immediate,
+            document,
+            vec![Instruction::CallPrimitive(function)],
+        )
+    }
+
+    /// define a batch of primitive words from a table
+    ///
+    /// A compact alternative to repeated `define_primitive_word`
+    /// calls for modules and embedders registering many words.
+    pub fn define_primitives(&mut self, table: &[PrimitiveWordDef<T, E>]) {
+        for (name, immediate, document, function) in table {
+            self.define_primitive_word(name, *immediate, document, *function);
+        }
+    }
+
+    /// define a primitive word that has no side effect beyond the
+    /// data stack
+    ///
+    /// The word is registered exactly like `define_primitive_word`
+    /// and additionally marked pure, so that optimizers and analysis
+    /// tools may evaluate or reorder it freely.
+    pub fn define_pure_primitive_word(
+        &mut self,
+        name: &str,
+        immediate: bool,
+        document: &str,
+        function: PrimitiveWordFunction<T, E>,
+    ) -> CodeAddress {
+        let code = self.define_primitive_word(name, immediate, document, function);
+        if let Some(word) = self.word_dictionary.find_word_mut(name) {
+            word.set_pure();
+        }
+        code
+    }
+
+    /// define a word from a raw instruction body, returning its code
+    /// address
+    ///
+    /// `Return` and the word terminator are appended automatically.
+    pub fn define_word_with_instructions(
+        &mut self,
+        name: &str,
+        immediate: bool,
+        document: &str,
+        instructions: Vec<Instruction<T, E>>,
+    ) -> CodeAddress {
+        let code = self.code_buffer.here();
+        for instruction in instructions {
+            self.code_buffer.push(instruction);
+        }
+        self.code_buffer.push(Instruction::Return);
+        self.code_buffer.push(Instruction::WordTerminator);
+        let mut word = Word::new(code);
+        if immediate {
+            word.set_immediate();
+        }
+        word.set_document(String::from(document));
+        self.word_dictionary.define(String::from(name), word);
+        code
+    }
+
+    /// compile a branch taken when the popped value is true
+    ///
+    /// # Arguments
+    /// * `target` - jump target of the branch
+    pub fn compile_branch(&mut self, target: CodeAddress) {
+        self.compile_instruction(Instruction::Branch(target));
+    }
+
+    /// compile a branch taken when the popped value is false
+    ///
+    /// Control flow words pick the polarity that spares a negation
+    /// at run time.
+    ///
+    /// # Arguments
+    /// * `target` - jump target of the branch
+    pub fn compile_branch_zero(&mut self, target: CodeAddress) {
+        self.compile_instruction(Instruction::BranchZero(target));
+    }
+
+    /// append an instruction to the code buffer, recording debug info
+    pub fn compile_instruction(&mut self, instruction: Instruction<T, E>) {
+        let code = self.code_buffer.here();
+        self.code_buffer.push(instruction);
+        self.debug_info_store.add(code, self.current_position.clone());
+    }
+
+    /// start compiling a word definition
+    ///
+    /// # Arguments
+    /// * `name` - name of the new word; ignored for anonymous words
+    /// * `anonymous` - when true, the completed word is not entered
+    ///   into the dictionary and its address is pushed instead
+    pub fn begin_word_def(&mut self, name: String, anonymous: bool) -> Result<(), VmErrorReason<E>> {
+        if self.state != VmState::Interpretation {
+            return Err(VmErrorReason::WordError("nested word definition"));
+        }
+        let mut word = Word::new(self.code_buffer.here());
+        if self.auto_doc_comment {
+            if let Some(document) = self.last_comment.take() {
+                word.set_document(document);
+            }
+        }
+        self.word_dictionary.reserve_word_def(name, word);
+        self.local_dictionary.clear();
+        self.anonymous_definition = anonymous;
+        self.state = VmState::Compilation;
+        Ok(())
+    }
+
+    /// finish compiling a word definition
+    pub fn end_word_def(&mut self) -> Result<(), VmErrorReason<E>> {
+        if self.state != VmState::Compilation && self.state != VmState::RecursableCompilation {
+            return Err(VmErrorReason::WordError("no word definition to end"));
+        }
+        self.compile_instruction(Instruction::Return);
+        self.compile_instruction(Instruction::WordTerminator);
+        if self.anonymous_definition {
+            let code = self
+                .word_dictionary
+                .reserved_word_def()
+                .map(|(_, word)| word.code());
+            self.word_dictionary.cancel_word_def();
+            if let Some(code) = code {
+                self.data_stack.push(Rc::new(Value::CodeAddress(code)));
+            }
+        } else {
+            self.word_dictionary.complate_word_def();
+        }
+        self.local_dictionary.clear();
+        self.anonymous_definition = false;
+        self.state = VmState::Interpretation;
+        Ok(())
+    }
+
+    /// read the next token and require it to be a symbol
+    pub fn next_symbol_token(&mut self) -> Result<String, VmErrorReason<E>> {
+        match self.input_stream.next_token()? {
+            None => Err(VmErrorReason::WordError("unexpected end of input")),
+            Some(token) => match token.value_token {
+                ValueToken::Symbol(name) => Ok(name),
+                _ => Err(VmErrorReason::TypeMismatchError("symbol")),
+            },
+        }
+    }
+
+    /// execute the word starting at the given address
+    ///
+    /// Pushes a top level call frame; the actual execution happens in
+    /// the `exec` loop.
+    pub fn execute_word(&mut self, code: CodeAddress) {
+        let frame = CallFrame::new(
+            CodeAddress(Address::Root),
+            self.env_stack.here(),
+            self.data_stack.here(),
+            self.scratch_stack.here(),
+        );
+        self.return_stack.push(frame);
+        self.pc = code;
+    }
+
+    /// return from the word currently executing without running the
+    /// rest of its body
+    ///
+    /// This backs words like `marker` that truncate the code buffer
+    /// under their own feet: the remaining instructions of the body
+    /// are gone, so the frame is unwound here instead of by `Return`.
+    pub fn return_from_current_word(&mut self) -> Result<(), VmErrorReason<E>> {
+        let frame = self
+            .return_stack
+            .pop()
+            .map_err(VmErrorReason::ReturnStackAccessError)?;
+        self.scratch_stack
+            .rollback(frame.scratch_base())
+            .map_err(VmErrorReason::ScratchStackAccessError)?;
+        self.env_stack
+            .rollback(frame.env_base())
+            .map_err(VmErrorReason::EnvStackAccessError)?;
+        self.pc = frame.return_address();
+        Ok(())
+    }
+
+    /// switch the input to the given stream, stacking the current one
+    pub fn call_script(&mut self, stream: Box<dyn TokenIterator>) {
+        let old = std::mem::replace(&mut self.input_stream, stream);
+        self.script_call_stack.push(old);
+    }
+
+    /// whether comparison words require operands of the same variant
+    pub fn strict_compare(&self) -> bool {
+        self.strict_compare
+    }
+
+    /// require comparison operands of the same variant, or fall back
+    /// to the lenient total order on `Value`
+    pub fn set_strict_compare(&mut self, strict: bool) {
+        self.strict_compare = strict;
+    }
+
+    /// number of stacked input streams
+    pub fn script_depth(&self) -> usize {
+        self.script_call_stack.len()
+    }
+
+    /// limit the script call depth; `None` removes the limit
+    ///
+    /// The limit guards against runaway `include` or `evaluate`
+    /// recursion exhausting memory.
+    pub fn set_max_script_depth(&mut self, max: Option<usize>) {
+        self.max_script_depth = max;
+    }
+
+    /// cap the number of instructions `exec` may still execute;
+    /// `None` removes the cap
+    ///
+    /// The budget guards against runaway loops in untrusted scripts.
+    pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+        self.instruction_budget = budget;
+    }
+
+    /// cap the data stack depth; `None` removes the cap
+    ///
+    /// The depth is checked between instructions, so a single
+    /// instruction may overshoot the limit by one value.
+    pub fn set_data_stack_limit(&mut self, limit: Option<usize>) {
+        self.data_stack_limit = limit;
+    }
+
+    /// whether a line comment right before a definition becomes its
+    /// documentation
+    pub fn auto_doc_comment(&self) -> bool {
+        self.auto_doc_comment
+    }
+
+    /// capture a line comment immediately preceding a `:` definition
+    /// as the documentation of the defined word
+    ///
+    /// Any other token between the comment and the definition drops
+    /// the comment again.
+    pub fn set_auto_doc_comment(&mut self, enabled: bool) {
+        self.auto_doc_comment = enabled;
+    }
+
+    /// like `call_script`, but errors when the configured script
+    /// call depth would be exceeded
+    pub fn try_call_script(
+        &mut self,
+        stream: Box<dyn TokenIterator>,
+    ) -> Result<(), VmErrorReason<E>> {
+        if let Some(max) = self.max_script_depth {
+            if self.script_call_stack.len() >= max {
+                return Err(VmErrorReason::ScriptCallDepthExceeded(max));
+            }
+        }
+        self.call_script(stream);
+        Ok(())
+    }
+
+    /// record a module as being included
+    ///
+    /// Errors when the module is already on the in-progress include
+    /// chain, which would loop until the script call stack blows up.
+    /// The chain entry is dropped when the module's input ends.
+    pub fn begin_include(&mut self, name: &str) -> Result<(), VmErrorReason<E>> {
+        if self.include_chain.iter().any(|n| n == name) {
+            return Err(VmErrorReason::ResourceError(
+                ResourceErrorReason::IncludeCycle(String::from(name)),
+            ));
+        }
+        self.include_chain.push(String::from(name));
+        Ok(())
+    }
+
+    /// drop the include chain entry of a module that was never entered
+    ///
+    /// The normal pop happens when the module's input ends; a
+    /// `begin_include` whose script call failed never gets there, and
+    /// the stale entry would falsely report a cycle on the next
+    /// include of the same module.
+    pub fn cancel_include(&mut self, name: &str) {
+        if self.include_chain.last().map(String::as_str) == Some(name) {
+            self.include_chain.pop();
+        }
+    }
+
+    /// drop dictionary and debug info entries past the end of the
+    /// code buffer
+    ///
+    /// `forget` keeps both stores in sync on its own; this is the
+    /// recovery path after the code buffer was rolled back directly.
+    pub fn compact(&mut self) {
+        let len = self.code_buffer.len();
+        self.word_dictionary.compact(len);
+        self.debug_info_store
+            .remove_from(CodeAddress::from_index(len));
+    }
+
+    /// clear every stack and return the machine to its initial state
+    ///
+    /// The dictionary, the code buffer and the data buffer are kept.
+    pub fn reset_all(&mut self) {
+        self.reset_execution();
+        self.input_stream = Box::new(EmptyTokenStream::new());
+        self.script_call_stack.clear();
+        self.include_chain.clear();
+    }
+
+    /// clear every stack and return to interpretation state, but keep
+    /// the current input streams
+    ///
+    /// This is the recovery step of an interactive session: after an
+    /// error the machine continues reading where it stopped.
+    pub fn reset_execution(&mut self) {
+        self.data_stack.rollback(0).ok();
+        self.return_stack.rollback(0).ok();
+        self.scratch_stack.rollback(0).ok();
+        self.env_stack.rollback(0).ok();
+        self.controlflow_stack.rollback(0).ok();
+        self.word_dictionary.cancel_word_def();
+        self.local_dictionary.clear();
+        self.state = VmState::Interpretation;
+        self.exec_state = VmExecutionState::NormalState;
+        self.pc = CodeAddress(Address::Root);
+        self.anonymous_definition = false;
+    }
+
+    /// run the machine until the input is exhausted
+    ///
+    /// After a `TrapError`, calling `exec` again resumes execution at
+    /// the instruction following the trap.
+    pub fn exec(&mut self) -> Result<(), VmErrorReason<E>> {
+        loop {
+            if self.exec_state == VmExecutionState::FinishState {
+                return Ok(());
+            }
+            if self.return_stack.here() > 0 {
+                self.step()?;
+            } else {
+                let token = if self.auto_doc_comment {
+                    self.next_noncomment_token()?
+                } else {
+                    self.input_stream.next_token()?
+                };
+                match token {
+                    Some(token) => self.handle_token(token)?,
+                    None => match self.script_call_stack.pop() {
+                        Some(stream) => {
+                            let finished = std::mem::replace(&mut self.input_stream, stream);
+                            if self.include_chain.last().map(String::as_str)
+                                == Some(finished.script_name())
+                            {
+                                self.include_chain.pop();
+                            }
+                        }
+                        None => {
+                            // an input ending mid-definition must not
+                            // leave the half-finished word reserved
+                            if self.state != VmState::Interpretation {
+                                self.word_dictionary.cancel_word_def();
+                                self.local_dictionary.clear();
+                                self.state = VmState::Interpretation;
+                                return Err(VmErrorReason::WordError("unterminated definition"));
+                            }
+                            return Ok(());
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    /// push the given arguments on the environment stack, then run
+    pub fn exec_with_args(&mut self, args: &[String]) -> Result<(), VmErrorReason<E>> {
+        for arg in args {
+            self.env_stack.push(Rc::new(Value::StrValue(arg.clone())));
+        }
+        self.exec()
+    }
+
+    /// push pre-built argument values on the environment stack, then run
+    ///
+    /// Unlike `exec_with_args` this accepts any value variant, so an
+    /// embedder can seed the program with ints or floats directly.
+    pub fn exec_with_typed_args(&mut self, args: Vec<Rc<Value<T>>>) -> Result<(), VmErrorReason<E>> {
+        for arg in args {
+            self.env_stack.push(arg);
+        }
+        self.exec()
+    }
+
+    /// next non-comment token, remembering the body of the last
+    /// comment seen for `auto_doc_comment`
+    fn next_noncomment_token(&mut self) -> Result<Option<Token>, TokenizerError> {
+        loop {
+            match self.input_stream.next_token_with_comment()? {
+                Some(TokenWithComment::Comment(t)) => {
+                    if let ValueToken::StrValue(body) = t.value_token {
+                        self.last_comment = Some(String::from(body.trim()));
+                        self.last_comment_fresh = true;
+                    }
+                }
+                Some(TokenWithComment::Token(t)) => {
+                    // the comment only documents the token directly
+                    // after it; one token later it goes stale
+                    if self.last_comment_fresh {
+                        self.last_comment_fresh = false;
+                    } else {
+                        self.last_comment = None;
+                    }
+                    return Ok(Some(t));
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// handle one token from the input
+    fn handle_token(&mut self, token: Token) -> Result<(), VmErrorReason<E>> {
+        self.current_position = CodePosition::new(
+            String::from(self.input_stream.script_name()),
+            token.line_number,
+            token.column_number,
+        );
+        match self.state {
+            VmState::Interpretation => self.interpret_token(token),
+            VmState::Compilation | VmState::RecursableCompilation => self.compile_token(token),
+        }
+    }
+
+    /// execute one token immediately
+    fn interpret_token(&mut self, token: Token) -> Result<(), VmErrorReason<E>> {
+        match token.value_token {
+            ValueToken::IntValue(v) => {
+                self.data_stack.push(Rc::new(Value::IntValue(v)));
+                Ok(())
+            }
+            ValueToken::FloatValue(v) => {
+                self.data_stack.push(Rc::new(Value::FloatValue(v)));
+                Ok(())
+            }
+            ValueToken::StrValue(v) => {
+                let v = self.intern_string(v);
+                self.data_stack.push(v);
+                Ok(())
+            }
+            ValueToken::Symbol(name) => match self.word_dictionary.find_word(&name) {
+                Some(word) => {
+                    let code = word.code();
+                    self.execute_word(code);
+                    Ok(())
+                }
+                None => Err(VmErrorReason::UndefinedWord(name)),
+            },
+        }
+    }
+
+    /// compile one token into the current definition
+    fn compile_token(&mut self, token: Token) -> Result<(), VmErrorReason<E>> {
+        match token.value_token {
+            ValueToken::IntValue(v) => {
+                self.compile_instruction(Instruction::Push(Rc::new(Value::IntValue(v))));
+                Ok(())
+            }
+            ValueToken::FloatValue(v) => {
+                self.compile_instruction(Instruction::Push(Rc::new(Value::FloatValue(v))));
+                Ok(())
+            }
+            ValueToken::StrValue(v) => {
+                let v = self.intern_string(v);
+                self.compile_instruction(Instruction::Push(v));
+                Ok(())
+            }
+            ValueToken::Symbol(name) => self.compile_symbol(name),
+        }
+    }
+
+    /// compile one symbol into the current definition
+    fn compile_symbol(&mut self, name: String) -> Result<(), VmErrorReason<E>> {
+        if let Some(i) = self.local_dictionary.find(&name) {
+            self.compile_instruction(Instruction::LocalRef(i));
+            return Ok(());
+        }
+        if let Some(word) = self.word_dictionary.find_word(&name) {
+            let code = word.code();
+            if word.immediate() {
+                self.execute_word(code);
+            } else {
+                self.compile_instruction(Instruction::Call(code));
+            }
+            return Ok(());
+        }
+        if self.state == VmState::RecursableCompilation {
+            if let Some((reserved_name, word)) = self.word_dictionary.reserved_word_def() {
+                if reserved_name == &name {
+                    let code = word.code();
+                    self.compile_instruction(Instruction::Call(code));
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(mut handler) = self.unknown_symbol_handler.take() {
+            let resolved = handler(&name);
+            self.unknown_symbol_handler = Some(handler);
+            if let Some(instruction) = resolved {
+                self.compile_instruction(instruction);
+                return Ok(());
+            }
+        }
+        Err(VmErrorReason::UndefinedWord(name))
+    }
+
+    /// execute one instruction
+    fn step(&mut self) -> Result<(), VmErrorReason<E>> {
+        if let Some(limit) = self.data_stack_limit {
+            if self.data_stack.here() > limit {
+                return Err(VmErrorReason::DataStackLimitExceeded(limit));
+            }
+        }
+        if let Some(budget) = self.instruction_budget.as_mut() {
+            if *budget == 0 {
+                return Err(VmErrorReason::InstructionBudgetExceeded);
+            }
+            *budget -= 1;
+        }
+        let instruction = self
+            .code_buffer
+            .get(self.pc)
+            .map_err(VmErrorReason::CodeBufferAccessError)?
+            .clone();
+        match instruction {
+            Instruction::Push(v) => {
+                self.data_stack.push(v);
+                self.pc = self.pc.next();
+                Ok(())
+            }
+            Instruction::Call(code) => {
+                let frame = CallFrame::new(
+                    self.pc.next(),
+                    self.env_stack.here(),
+                    self.data_stack.here(),
+                    self.scratch_stack.here(),
+                );
+                self.return_stack.push(frame);
+                self.pc = code;
+                Ok(())
+            }
+            Instruction::CallPrimitive(function) => {
+                self.pc = self.pc.next();
+                function(self)
+            }
+            Instruction::Jump(code) => {
+                self.pc = code;
+                Ok(())
+            }
+            Instruction::Branch(code) => {
+                let v = self.data_stack.pop()?;
+                if v.is_true() {
+                    self.pc = code;
+                } else {
+                    self.pc = self.pc.next();
+                }
+                Ok(())
+            }
+            Instruction::BranchZero(code) => {
+                let v = self.data_stack.pop()?;
+                if v.is_true() {
+                    self.pc = self.pc.next();
+                } else {
+                    self.pc = code;
+                }
+                Ok(())
+            }
+            Instruction::Exec => {
+                let v = self.data_stack.pop()?;
+                match v.as_ref() {
+                    Value::CodeAddress(code) => {
+                        let frame = CallFrame::new(
+                            self.pc.next(),
+                            self.env_stack.here(),
+                            self.data_stack.here(),
+                            self.scratch_stack.here(),
+                        );
+                        self.return_stack.push(frame);
+                        self.pc = *code;
+                        Ok(())
+                    }
+                    _ => Err(VmErrorReason::TypeMismatchError("execution token")),
+                }
+            }
+            Instruction::Return => {
+                let frame = self
+                    .return_stack
+                    .pop()
+                    .map_err(VmErrorReason::ReturnStackAccessError)?;
+                // a leftover `>r` would silently leak into the caller
+                if self.scratch_stack.here() != frame.scratch_base() {
+                    return Err(VmErrorReason::WordError(
+                        "unbalanced scratch stack in definition",
+                    ));
+                }
+                self.env_stack
+                    .rollback(frame.env_base())
+                    .map_err(VmErrorReason::EnvStackAccessError)?;
+                self.pc = frame.return_address();
+                Ok(())
+            }
+            Instruction::WordTerminator => {
+                Err(VmErrorReason::WordError("word terminator executed"))
+            }
+            Instruction::LocalDef => {
+                let v = self.data_stack.pop()?;
+                self.env_stack.push(v);
+                self.pc = self.pc.next();
+                Ok(())
+            }
+            Instruction::LocalRef(i) => {
+                let frame = *self
+                    .return_stack
+                    .pick(0)
+                    .map_err(VmErrorReason::ReturnStackAccessError)?;
+                let v = Rc::clone(
+                    self.env_stack
+                        .get(frame.env_base() + i)
+                        .map_err(VmErrorReason::EnvStackAccessError)?,
+                );
+                self.data_stack.push(v);
+                self.pc = self.pc.next();
+                Ok(())
+            }
+            Instruction::ScratchPush => {
+                let v = self.data_stack.pop()?;
+                self.scratch_stack.push(v);
+                self.pc = self.pc.next();
+                Ok(())
+            }
+            Instruction::ScratchPop => {
+                let v = self
+                    .scratch_stack
+                    .pop()
+                    .map_err(VmErrorReason::ScratchStackAccessError)?;
+                self.data_stack.push(v);
+                self.pc = self.pc.next();
+                Ok(())
+            }
+            Instruction::ScratchCopy => {
+                let v = Rc::clone(
+                    self.scratch_stack
+                        .pick(0)
+                        .map_err(VmErrorReason::ScratchStackAccessError)?,
+                );
+                self.data_stack.push(v);
+                self.pc = self.pc.next();
+                Ok(())
+            }
+            Instruction::Trap(reason) => {
+                self.pc = self.pc.next();
+                Err(VmErrorReason::TrapError(reason))
+            }
+            Instruction::LongJump(code) => {
+                let v = self.data_stack.pop()?;
+                let frame = *self
+                    .return_stack
+                    .pick(0)
+                    .map_err(VmErrorReason::ReturnStackAccessError)?;
+                self.data_stack.rollback(frame.data_base())?;
+                self.data_stack.push(v);
+                self.pc = code;
+                Ok(())
+            }
+            Instruction::LongJumpN(code, n) => {
+                let kept = self.data_stack.drain_top(n)?;
+                let frame = *self
+                    .return_stack
+                    .pick(0)
+                    .map_err(VmErrorReason::ReturnStackAccessError)?;
+                self.data_stack.rollback(frame.data_base())?;
+                for v in kept.into_iter().rev() {
+                    self.data_stack.push(v);
+                }
+                self.pc = code;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::resource::BufferResources;
+    use crate::lang::tokenizer::new_token_stream_from_string;
+    use std::rc::Rc;
+
+    type TestVm = Vm<i32, i32>;
+
+    fn new_test_vm() -> TestVm {
+        Vm::new(BufferResources::new())
+    }
+
+    fn run_script(vm: &mut TestVm, script: &str) -> Result<(), VmErrorReason<i32>> {
+        vm.call_script(Box::new(new_token_stream_from_string(
+            String::from(script),
+            String::from("test"),
+        )));
+        vm.exec()
+    }
+
+    fn pop_int(vm: &mut TestVm) -> crate::lang::VmInt {
+        match *vm.data_stack_mut().pop().unwrap() {
+            Value::IntValue(i) => i,
+            ref v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_push_literals() {
+        let mut vm = new_test_vm();
+        run_script(&mut vm, "1 2").unwrap();
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_pick_roll_out_of_range() {
+        let mut vm = new_test_vm();
+        run_script(&mut vm, "1 2").unwrap();
+        assert_eq!(
+            vm.data_stack().pick(5).unwrap_err(),
+            DataStackErrorReason::PickOutOfRange(2, 5)
+        );
+        assert_eq!(
+            vm.data_stack_mut().roll(3).unwrap_err(),
+            DataStackErrorReason::RollOutOfRange(2, 3)
+        );
+    }
+
+    #[test]
+    fn test_compact_drops_stale_entries() {
+        let mut vm = new_test_vm();
+        vm.define_word_with_instructions("live", false, "", vec![Instruction::Return]);
+        // a rollback behind the VM's back leaves entries past the end
+        let stale = CodeAddress::from_index(vm.code_buffer().len() + 10);
+        vm.word_dictionary_mut()
+            .define(String::from("ghost"), Word::new(stale));
+        vm.debug_info_store_mut()
+            .add(stale, CodePosition::new(String::from("test"), 1, 1));
+        vm.compact();
+        assert!(vm.word_dictionary().find_word("ghost").is_none());
+        assert!(vm.word_dictionary().find_word("live").is_some());
+        let last = CodeAddress::from_index(vm.code_buffer().len() - 1);
+        let (name, _) = vm.word_dictionary().guess_name(last).unwrap();
+        assert_eq!(name, "live");
+        assert!(vm.debug_info_store().get(stale).is_none());
+    }
+
+    #[test]
+    fn test_undefined_word() {
+        let mut vm = new_test_vm();
+        match run_script(&mut vm, "no-such-word") {
+            Err(VmErrorReason::UndefinedWord(name)) => assert_eq!(name, "no-such-word"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_string_literal_interning() {
+        let mut vm = new_test_vm();
+        run_script(&mut vm, "\"abc\" \"abc\"").unwrap();
+        let a = vm.data_stack_mut().pop().unwrap();
+        let b = vm.data_stack_mut().pop().unwrap();
+        assert!(Rc::ptr_eq(&a, &b));
+        let mut vm = new_test_vm();
+        vm.set_interning_enabled(false);
+        run_script(&mut vm, "\"abc\" \"abc\"").unwrap();
+        let a = vm.data_stack_mut().pop().unwrap();
+        let b = vm.data_stack_mut().pop().unwrap();
+        assert!(!Rc::ptr_eq(&a, &b));
+        assert_eq!(*a, *b);
+    }
+
+    #[test]
+    fn test_primitive_word() {
+        let mut vm = new_test_vm();
+        vm.define_primitive_word("seven", false, "( -- 7 )", |vm| {
+            vm.data_stack_mut().push(Rc::new(Value::IntValue(7)));
+            Ok(())
+        });
+        run_script(&mut vm, "seven seven").unwrap();
+        assert_eq!(pop_int(&mut vm), 7);
+        assert_eq!(pop_int(&mut vm), 7);
+    }
+
+    #[test]
+    fn test_ext_value_type() {
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+        struct Ratio(i32, i32);
+        impl std::fmt::Display for Ratio {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}/{}", self.0, self.1)
+            }
+        }
+        // the blanket impl picks the type up from its derives alone
+        fn assert_ext_value<V: value::ExtValue>() {}
+        assert_ext_value::<Ratio>();
+        let mut vm: Vm<Ratio, i32> = Vm::new(BufferResources::new());
+        vm.data_stack_mut()
+            .push(Rc::new(Value::ExtValue(Ratio(1, 2))));
+        match &*vm.data_stack_mut().pop().unwrap() {
+            Value::ExtValue(r) => assert_eq!(*r, Ratio(1, 2)),
+            v => panic!("unexpected value: {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_define_primitives_table() {
+        let mut vm = new_test_vm();
+        vm.define_primitives(&[
+            ("one", false, "( -- 1 )", |vm| {
+                vm.data_stack_mut().push(Rc::new(Value::IntValue(1)));
+                Ok(())
+            }),
+            ("two", false, "( -- 2 )", |vm| {
+                vm.data_stack_mut().push(Rc::new(Value::IntValue(2)));
+                Ok(())
+            }),
+        ]);
+        run_script(&mut vm, "one two").unwrap();
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_user_trap() {
+        let mut vm = new_test_vm();
+        vm.define_word_with_instructions(
+            "t",
+            false,
+            "( -- 1 2 )",
+            vec![
+                Instruction::Push(Rc::new(Value::IntValue(1))),
+                Instruction::Trap(TrapReason::UserTrap),
+                Instruction::Push(Rc::new(Value::IntValue(2))),
+            ],
+        );
+        match run_script(&mut vm, "t") {
+            Err(VmErrorReason::TrapError(TrapReason::UserTrap)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        // execution resumes after the trap
+        vm.exec().unwrap();
+        assert_eq!(pop_int(&mut vm), 2);
+        assert_eq!(pop_int(&mut vm), 1);
+    }
+
+    #[test]
+    fn test_branch_polarities() {
+        let mut vm = new_test_vm();
+        // each word skips the push of 10 when its branch is taken
+        let base = usize::try_from(vm.code_buffer().here()).unwrap();
+        vm.define_word_with_instructions(
+            "bt",
+            false,
+            "flag -- ..",
+            vec![
+                Instruction::Branch(CodeAddress::from_index(base + 2)),
+                Instruction::Push(Rc::new(Value::IntValue(10))),
+                Instruction::Push(Rc::new(Value::IntValue(20))),
+            ],
+        );
+        let base = usize::try_from(vm.code_buffer().here()).unwrap();
+        vm.define_word_with_instructions(
+            "bz",
+            false,
+            "flag -- ..",
+            vec![
+                Instruction::BranchZero(CodeAddress::from_index(base + 2)),
+                Instruction::Push(Rc::new(Value::IntValue(10))),
+                Instruction::Push(Rc::new(Value::IntValue(20))),
+            ],
+        );
+        run_script(&mut vm, "1 bt").unwrap();
+        assert_eq!(pop_int(&mut vm), 20);
+        assert_eq!(vm.data_stack().here(), 0);
+        run_script(&mut vm, "0 bt").unwrap();
+        assert_eq!(pop_int(&mut vm), 20);
+        assert_eq!(pop_int(&mut vm), 10);
+        run_script(&mut vm, "0 bz").unwrap();
+        assert_eq!(pop_int(&mut vm), 20);
+        assert_eq!(vm.data_stack().here(), 0);
+        run_script(&mut vm, "1 bz").unwrap();
+        assert_eq!(pop_int(&mut vm), 20);
+        assert_eq!(pop_int(&mut vm), 10);
+    }
+
+    #[test]
+    fn test_long_jump_n() {
+        let mut vm = new_test_vm();
+        let target = vm.define_word_with_instructions(
+            "handler",
+            false,
+            "( -- 99 )",
+            vec![Instruction::Push(Rc::new(Value::IntValue(99)))],
+        );
+        vm.define_word_with_instructions(
+            "w",
+            false,
+            "( -- 10 20 99 )",
+            vec![
+                Instruction::Push(Rc::new(Value::IntValue(1))),
+                Instruction::Push(Rc::new(Value::IntValue(10))),
+                Instruction::Push(Rc::new(Value::IntValue(20))),
+                Instruction::LongJumpN(target, 2),
+                // never reached
+                Instruction::Push(Rc::new(Value::IntValue(3))),
+            ],
+        );
+        run_script(&mut vm, "5 w").unwrap();
+        // the two preserved values survive the unwind in their
+        // original order
+        assert_eq!(pop_int(&mut vm), 99);
+        assert_eq!(pop_int(&mut vm), 20);
+        assert_eq!(pop_int(&mut vm), 10);
+        assert_eq!(pop_int(&mut vm), 5);
+        assert_eq!(vm.data_stack().here(), 0);
+    }
+
+    #[test]
+    fn test_exec_with_args() {
+        let mut vm = new_test_vm();
+        vm.exec_with_args(&[String::from("a"), String::from("b")]).unwrap();
+        assert_eq!(vm.env_stack().here(), 2);
+        assert_eq!(
+            **vm.env_stack().get(0).unwrap(),
+            Value::StrValue(String::from("a"))
+        );
+    }
+
+    #[test]
+    fn test_reset_all() {
+        let mut vm = new_test_vm();
+        run_script(&mut vm, "1 2 3").unwrap();
+        vm.begin_word_def(String::from("w"), false).unwrap();
+        vm.reset_all();
+        assert_eq!(vm.data_stack().here(), 0);
+        assert_eq!(vm.state(), VmState::Interpretation);
+        assert!(vm.word_dictionary().reserved_word_def().is_none());
+    }
+}
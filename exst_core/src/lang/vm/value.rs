@@ -0,0 +1,166 @@
+//! runtime values
+//!
+//! Every cell of the data stack, the environment stack and the data
+//! buffer holds an `Rc<Value<T>>`. The type parameter `T` is an
+//! extension point for embedders that need an extra value type.
+
+use super::buffer::CodeAddress;
+use super::buffer::DataAddress;
+use crate::lang::VmInt;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// bundle of the bounds an extra value type needs
+///
+/// The machine is generic over an embedder defined value type `T`
+/// carried in `Value::ExtValue`. The word set requires `Debug`,
+/// `Display` and `Ord` of it in various places; this trait names the
+/// bundle so an embedder plugging in, say, a rational number type
+/// only has to satisfy one bound. A blanket impl covers every type
+/// with the right traits — there is nothing to implement by hand.
+pub trait ExtValue: fmt::Debug + fmt::Display + Ord {}
+impl<T: fmt::Debug + fmt::Display + Ord> ExtValue for T {}
+
+/// a runtime value
+#[derive(Debug, Clone)]
+pub enum Value<T> {
+    /// integer
+    IntValue(VmInt),
+    /// float
+    FloatValue(f64),
+    /// string
+    StrValue(String),
+    /// address into the code buffer (an execution token)
+    CodeAddress(CodeAddress),
+    /// address into the data buffer
+    DataAddress(DataAddress),
+    /// embedder defined extra value
+    ExtValue(T),
+}
+impl<T> Value<T> {
+    /// rank of the variant, used to order values of different variants
+    fn variant_rank(&self) -> u8 {
+        match self {
+            Value::IntValue(_) => 0,
+            Value::FloatValue(_) => 1,
+            Value::StrValue(_) => 2,
+            Value::CodeAddress(_) => 3,
+            Value::DataAddress(_) => 4,
+            Value::ExtValue(_) => 5,
+        }
+    }
+    /// numeric tag of the variant, used by typed data cells
+    pub fn type_id(&self) -> i32 {
+        i32::from(self.variant_rank())
+    }
+    /// true unless the value is the integer zero
+    pub fn is_true(&self) -> bool {
+        !matches!(self, Value::IntValue(0))
+    }
+    /// the value as a character, when it is an integer holding
+    /// a valid code point
+    pub fn try_into_char(&self) -> Option<char> {
+        match self {
+            Value::IntValue(i) => u32::try_from(*i).ok().and_then(std::char::from_u32),
+            _ => None,
+        }
+    }
+    /// total order over values
+    ///
+    /// Unlike `f64`'s partial order this places NaN after every
+    /// other float, so sorts and ordered containers stay
+    /// deterministic when a NaN reaches them.
+    pub fn total_cmp(&self, other: &Self) -> Ordering
+    where
+        T: Ord,
+    {
+        match (self, other) {
+            (Value::IntValue(a), Value::IntValue(b)) => a.cmp(b),
+            (Value::FloatValue(a), Value::FloatValue(b)) => a.total_cmp(b),
+            (Value::StrValue(a), Value::StrValue(b)) => a.cmp(b),
+            (Value::CodeAddress(a), Value::CodeAddress(b)) => a.cmp(b),
+            (Value::DataAddress(a), Value::DataAddress(b)) => a.cmp(b),
+            (Value::ExtValue(a), Value::ExtValue(b)) => a.cmp(b),
+            (a, b) => a.variant_rank().cmp(&b.variant_rank()),
+        }
+    }
+}
+impl<T: Ord> PartialEq for Value<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl<T: Ord> Eq for Value<T> {}
+impl<T: Ord> PartialOrd for Value<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<T: Ord> Ord for Value<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.total_cmp(other)
+    }
+}
+impl<T: fmt::Display> fmt::Display for Value<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::IntValue(v) => write!(f, "{}", v),
+            Value::FloatValue(v) => write!(f, "{}", v),
+            Value::StrValue(v) => write!(f, "{}", v),
+            Value::CodeAddress(a) => write!(f, "code[{:?}]", a.0),
+            Value::DataAddress(a) => write!(f, "data[{:?}]", a.0),
+            Value::ExtValue(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type V = Value<i32>;
+
+    #[test]
+    fn test_is_true() {
+        assert!(!V::IntValue(0).is_true());
+        assert!(V::IntValue(1).is_true());
+        assert!(V::StrValue(String::from("")).is_true());
+    }
+
+    #[test]
+    fn test_ord() {
+        assert!(V::IntValue(1) < V::IntValue(2));
+        assert!(V::IntValue(100) < V::StrValue(String::from("a")));
+        assert!(V::StrValue(String::from("a")) < V::StrValue(String::from("b")));
+        assert_eq!(V::FloatValue(1.5), V::FloatValue(1.5));
+    }
+
+    #[test]
+    fn test_total_cmp_nan() {
+        let mut values = [
+            V::FloatValue(f64::NAN),
+            V::FloatValue(1.0),
+            V::FloatValue(f64::NAN),
+            V::FloatValue(-1.0),
+        ];
+        values.sort();
+        assert_eq!(values[0], V::FloatValue(-1.0));
+        assert_eq!(values[1], V::FloatValue(1.0));
+        assert!(matches!(values[2], V::FloatValue(f) if f.is_nan()));
+        assert!(matches!(values[3], V::FloatValue(f) if f.is_nan()));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{}", V::IntValue(3)), "3");
+        assert_eq!(format!("{}", V::StrValue(String::from("abc"))), "abc");
+    }
+
+    #[test]
+    fn test_try_into_char() {
+        assert_eq!(V::IntValue(65).try_into_char(), Some('A'));
+        assert_eq!(V::IntValue(-1).try_into_char(), None);
+        assert_eq!(V::StrValue(String::from("a")).try_into_char(), None);
+    }
+}
@@ -0,0 +1,491 @@
+//! word dictionary
+//!
+//! A `Word` is a named entry point into the code buffer. The
+//! `Dictionary` maps names to words and keeps an inverse mapping from
+//! code addresses back to names for dumps and disassembly. While a
+//! colon definition is being compiled, its word is *reserved* and only
+//! becomes findable once the definition completes.
+
+use super::buffer::CodeAddress;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+/// declared stack effect of a word, parsed from its documentation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackEffect {
+    /// number of values the word pops
+    pub inputs: usize,
+    /// number of values the word pushes
+    pub outputs: usize,
+}
+
+/// a named entry point into the code buffer
+#[derive(Debug, Clone)]
+pub struct Word {
+    code: CodeAddress,
+    immediate: bool,
+    pure: bool,
+    document: String,
+}
+impl Word {
+    /// create a new word
+    ///
+    /// # Arguments
+    /// * `code` - address of the first instruction
+    pub fn new(code: CodeAddress) -> Self {
+        Word {
+            code,
+            immediate: false,
+            pure: false,
+            document: String::new(),
+        }
+    }
+    /// address of the first instruction
+    pub fn code(&self) -> CodeAddress {
+        self.code
+    }
+    /// true if the word executes during compilation
+    pub fn immediate(&self) -> bool {
+        self.immediate
+    }
+    /// make the word execute during compilation
+    pub fn set_immediate(&mut self) {
+        self.immediate = true;
+    }
+    /// make the word compile normally again
+    pub fn clear_immediate(&mut self) {
+        self.immediate = false;
+    }
+    /// true if the word has no side effect beyond the data stack
+    pub fn is_pure(&self) -> bool {
+        self.pure
+    }
+    /// mark the word as free of side effects
+    pub fn set_pure(&mut self) {
+        self.pure = true;
+    }
+    /// documentation of the word
+    pub fn document(&self) -> &str {
+        &self.document
+    }
+    /// replace the documentation of the word
+    pub fn set_document(&mut self, document: String) {
+        self.document = document;
+    }
+    /// stack effect parsed from the documentation
+    ///
+    /// The part of the documentation before an optional ` : ` is read
+    /// as an effect like `a b -- c`. Docs without exactly one `--`,
+    /// or with an open-ended `..` item, yield None.
+    pub fn stack_effect(&self) -> Option<StackEffect> {
+        let effect = self.document.split(" : ").next().unwrap_or("");
+        let tokens: Vec<&str> = effect.split_whitespace().collect();
+        if tokens.iter().filter(|t| **t == "--").count() != 1 {
+            return None;
+        }
+        if tokens.contains(&"..") {
+            return None;
+        }
+        let position = tokens.iter().position(|t| *t == "--").unwrap();
+        Some(StackEffect {
+            inputs: position,
+            outputs: tokens.len() - position - 1,
+        })
+    }
+}
+
+/// snapshot of the defined words, taken by `Dictionary::checkpoint`
+///
+/// The full entries are kept, not just the names, so a word that is
+/// redefined after the checkpoint rolls back to its old definition
+/// instead of keeping one that points at truncated code.
+#[derive(Debug, Clone)]
+pub struct DictCheckpoint {
+    dict: HashMap<String, Word>,
+    inverse_dict: BTreeMap<usize, String>,
+}
+
+/// the word dictionary
+///
+/// Word names live in a flat map; vocabularies are realized as a
+/// `vocab::name` prefix on the stored key. The search order decides
+/// which prefixes `find_word` tries before falling back to the plain
+/// name, and new definitions go into the top vocabulary of the order.
+#[derive(Debug)]
+pub struct Dictionary {
+    dict: HashMap<String, Word>,
+    inverse_dict: BTreeMap<usize, String>,
+    reserved: Option<(String, Word)>,
+    last_completed: Option<String>,
+    vocabularies: HashSet<String>,
+    search_order: Vec<String>,
+}
+impl Dictionary {
+    /// create an empty dictionary
+    pub fn new() -> Self {
+        Dictionary {
+            dict: HashMap::new(),
+            inverse_dict: BTreeMap::new(),
+            reserved: None,
+            last_completed: None,
+            vocabularies: HashSet::new(),
+            search_order: Vec::new(),
+        }
+    }
+    /// create a vocabulary under the given name
+    pub fn create_vocabulary(&mut self, name: String) {
+        self.vocabularies.insert(name);
+    }
+    /// push a vocabulary onto the search order
+    ///
+    /// Returns false when no vocabulary of that name was created.
+    pub fn push_search_order(&mut self, name: &str) -> bool {
+        if self.vocabularies.contains(name) {
+            self.search_order.push(String::from(name));
+            true
+        } else {
+            false
+        }
+    }
+    /// pop the most recently pushed vocabulary from the search order
+    ///
+    /// Returns false when the search order is empty.
+    pub fn pop_search_order(&mut self) -> bool {
+        self.search_order.pop().is_some()
+    }
+    /// the vocabulary new definitions currently go into
+    pub fn current_vocabulary(&self) -> Option<&String> {
+        self.search_order.last()
+    }
+    /// the key a definition of the given name would be stored under
+    fn qualify(&self, name: String) -> String {
+        match self.search_order.last() {
+            Some(v) if !name.contains("::") => format!("{}::{}", v, name),
+            _ => name,
+        }
+    }
+    /// the stored key the given name resolves to, following the
+    /// search order before falling back to the plain name
+    fn resolve_key(&self, name: &str) -> Option<String> {
+        if !name.contains("::") {
+            for v in self.search_order.iter().rev() {
+                let key = format!("{}::{}", v, name);
+                if self.dict.contains_key(&key) {
+                    return Some(key);
+                }
+            }
+        }
+        if self.dict.contains_key(name) {
+            Some(String::from(name))
+        } else {
+            None
+        }
+    }
+    /// define a word immediately, without going through reservation
+    pub fn define(&mut self, name: String, word: Word) {
+        let name = self.qualify(name);
+        if let Ok(i) = usize::try_from(word.code()) {
+            self.inverse_dict.insert(i, name.clone());
+        }
+        self.last_completed = Some(name.clone());
+        self.dict.insert(name, word);
+    }
+    /// reserve a word for the definition being compiled
+    ///
+    /// The word is not findable until `complate_word_def` is called.
+    /// A reservation replaces any previous one.
+    pub fn reserve_word_def(&mut self, name: String, word: Word) {
+        self.reserved = Some((name, word));
+    }
+    /// the reserved word, if any
+    pub fn reserved_word_def(&self) -> Option<&(String, Word)> {
+        self.reserved.as_ref()
+    }
+    /// drop the reservation without defining anything
+    pub fn cancel_word_def(&mut self) {
+        self.reserved = None;
+    }
+    /// move the reserved word into the dictionary
+    ///
+    /// An existing word under the same name is overwritten.
+    pub fn complate_word_def(&mut self) {
+        if let Some((name, word)) = self.reserved.take() {
+            self.define(name, word);
+        }
+    }
+    /// look up a word by name
+    pub fn find_word(&self, name: &str) -> Option<&Word> {
+        self.resolve_key(name).and_then(move |key| self.dict.get(&key))
+    }
+    /// look up a word by name for modification
+    pub fn find_word_mut(&mut self, name: &str) -> Option<&mut Word> {
+        let key = self.resolve_key(name)?;
+        self.dict.get_mut(&key)
+    }
+    /// the reserved word if any, otherwise the most recently defined word
+    pub fn last_word_mut(&mut self) -> Option<&mut Word> {
+        if let Some((_, word)) = self.reserved.as_mut() {
+            Some(word)
+        } else if let Some(name) = self.last_completed.as_ref() {
+            self.dict.get_mut(name)
+        } else {
+            None
+        }
+    }
+    /// name of the most recently defined word
+    pub fn last_word_name(&self) -> Option<&String> {
+        self.last_completed.as_ref()
+    }
+    /// the most recently completed word, ignoring any reservation
+    pub fn last_completed_word(&self) -> Option<&Word> {
+        self.last_completed.as_ref().and_then(|n| self.dict.get(n))
+    }
+    /// the word whose code exactly starts at the given address
+    pub fn find_name(&self, code: CodeAddress) -> Option<&String> {
+        usize::try_from(code)
+            .ok()
+            .and_then(|i| self.inverse_dict.get(&i))
+    }
+    /// the word whose code region the given address most likely
+    /// belongs to, with the start address of that word
+    pub fn guess_name(&self, code: CodeAddress) -> Option<(&String, CodeAddress)> {
+        let i = usize::try_from(code).ok()?;
+        self.inverse_dict
+            .range(..=i)
+            .next_back()
+            .map(|(k, v)| (v, CodeAddress::from_index(*k)))
+    }
+    /// all defined word names, ordered by code address
+    pub fn all_word_names(&self) -> Vec<(CodeAddress, String)> {
+        let mut names: Vec<(CodeAddress, String)> = self
+            .dict
+            .iter()
+            .map(|(name, word)| (word.code(), name.clone()))
+            .collect();
+        // the name breaks ties between words sharing a code address
+        // (aliases), keeping the order reproducible
+        names.sort_by(|(a_code, a_name), (b_code, b_name)| {
+            a_code.cmp(b_code).then_with(|| a_name.cmp(b_name))
+        });
+        names
+    }
+    /// drop every entry pointing at or past the given code length
+    ///
+    /// This restores consistency between the dictionary and a code
+    /// buffer that was truncated behind its back.
+    pub fn compact(&mut self, len: usize) {
+        let end = CodeAddress::from_index(len);
+        self.dict.retain(|_, word| word.code() < end);
+        self.inverse_dict.split_off(&len);
+        if let Some(name) = self.last_completed.as_ref() {
+            if !self.dict.contains_key(name) {
+                self.last_completed = None;
+            }
+        }
+    }
+    /// capture the current set of defined words
+    ///
+    /// Pair a checkpoint with the code buffer length so a later
+    /// `rollback_to` plus a buffer truncation restores the whole
+    /// machine.
+    pub fn checkpoint(&self) -> DictCheckpoint {
+        DictCheckpoint {
+            dict: self.dict.clone(),
+            inverse_dict: self.inverse_dict.clone(),
+        }
+    }
+    /// restore the words captured by the checkpoint
+    ///
+    /// Words defined after the checkpoint are removed; a name that
+    /// was redefined after it goes back to its captured definition.
+    pub fn rollback_to(&mut self, cp: &DictCheckpoint) {
+        self.dict = cp.dict.clone();
+        self.inverse_dict = cp.inverse_dict.clone();
+        if let Some(name) = self.last_completed.as_ref() {
+            if !self.dict.contains_key(name) {
+                self.last_completed = None;
+            }
+        }
+    }
+    /// remove every word whose code is at or after the given address
+    pub fn forget(&mut self, code: CodeAddress) {
+        self.dict.retain(|_, word| word.code() < code);
+        if let Ok(i) = usize::try_from(code) {
+            self.inverse_dict.split_off(&i);
+        }
+        if let Some(name) = self.last_completed.as_ref() {
+            if !self.dict.contains_key(name) {
+                self.last_completed = None;
+            }
+        }
+    }
+}
+impl Default for Dictionary {
+    fn default() -> Self {
+        Dictionary::new()
+    }
+}
+
+/// dictionary of the local variables of the definition being compiled
+#[derive(Debug)]
+pub struct LocalDictionary {
+    names: Vec<String>,
+}
+impl LocalDictionary {
+    /// create an empty dictionary
+    pub fn new() -> Self {
+        LocalDictionary { names: Vec::new() }
+    }
+    /// define a local, returning its index
+    pub fn define(&mut self, name: String) -> usize {
+        self.names.push(name);
+        self.names.len() - 1
+    }
+    /// index of a local
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+    /// all local names, in definition order
+    pub fn get_all_local_names(&self) -> &[String] {
+        &self.names
+    }
+    /// forget every local
+    pub fn clear(&mut self) {
+        self.names.clear();
+    }
+}
+impl Default for LocalDictionary {
+    fn default() -> Self {
+        LocalDictionary::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_and_complete() {
+        let mut d = Dictionary::new();
+        d.reserve_word_def(String::from("a"), Word::new(CodeAddress::from_index(0)));
+        assert!(d.find_word("a").is_none());
+        d.complate_word_def();
+        assert_eq!(d.find_word("a").unwrap().code(), CodeAddress::from_index(0));
+        // an existing word is silently overwritten
+        d.reserve_word_def(String::from("a"), Word::new(CodeAddress::from_index(5)));
+        d.complate_word_def();
+        assert_eq!(d.find_word("a").unwrap().code(), CodeAddress::from_index(5));
+    }
+
+    #[test]
+    fn test_stack_effect() {
+        let effect = |doc: &str| {
+            let mut w = Word::new(CodeAddress::from_index(0));
+            w.set_document(String::from(doc));
+            w.stack_effect()
+        };
+        assert_eq!(
+            effect("a b -- c : c = a + b"),
+            Some(StackEffect { inputs: 2, outputs: 1 })
+        );
+        assert_eq!(effect("-- addr"), Some(StackEffect { inputs: 0, outputs: 1 }));
+        assert_eq!(effect("--"), Some(StackEffect { inputs: 0, outputs: 0 }));
+        assert_eq!(effect("just a description"), None);
+        assert_eq!(effect("xu .. x0 u -- xu"), None);
+        assert_eq!(effect(""), None);
+    }
+
+    #[test]
+    fn test_inverse_lookup() {
+        let mut d = Dictionary::new();
+        d.define(String::from("a"), Word::new(CodeAddress::from_index(0)));
+        d.define(String::from("b"), Word::new(CodeAddress::from_index(4)));
+        assert_eq!(d.find_name(CodeAddress::from_index(4)).unwrap(), "b");
+        assert_eq!(d.find_name(CodeAddress::from_index(5)), None);
+        let (name, code) = d.guess_name(CodeAddress::from_index(6)).unwrap();
+        assert_eq!(name, "b");
+        assert_eq!(code, CodeAddress::from_index(4));
+        let (name, _) = d.guess_name(CodeAddress::from_index(3)).unwrap();
+        assert_eq!(name, "a");
+    }
+
+    #[test]
+    fn test_all_word_names() {
+        let mut d = Dictionary::new();
+        d.define(String::from("b"), Word::new(CodeAddress::from_index(4)));
+        d.define(String::from("a"), Word::new(CodeAddress::from_index(0)));
+        let names: Vec<String> = d.all_word_names().into_iter().map(|(_, n)| n).collect();
+        assert_eq!(names, vec![String::from("a"), String::from("b")]);
+        // aliases share a code address; the name decides their order
+        d.define(String::from("z"), Word::new(CodeAddress::from_index(4)));
+        d.define(String::from("c"), Word::new(CodeAddress::from_index(4)));
+        let names: Vec<String> = d.all_word_names().into_iter().map(|(_, n)| n).collect();
+        assert_eq!(
+            names,
+            vec![
+                String::from("a"),
+                String::from("b"),
+                String::from("c"),
+                String::from("z")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forget() {
+        let mut d = Dictionary::new();
+        d.define(String::from("a"), Word::new(CodeAddress::from_index(0)));
+        d.define(String::from("b"), Word::new(CodeAddress::from_index(4)));
+        d.forget(CodeAddress::from_index(4));
+        assert!(d.find_word("a").is_some());
+        assert!(d.find_word("b").is_none());
+        assert_eq!(d.find_name(CodeAddress::from_index(4)), None);
+    }
+
+    #[test]
+    fn test_checkpoint_rollback() {
+        let mut d = Dictionary::new();
+        d.define(String::from("a"), Word::new(CodeAddress::from_index(0)));
+        let cp = d.checkpoint();
+        d.define(String::from("b"), Word::new(CodeAddress::from_index(4)));
+        d.define(String::from("c"), Word::new(CodeAddress::from_index(8)));
+        d.rollback_to(&cp);
+        assert!(d.find_word("a").is_some());
+        assert!(d.find_word("b").is_none());
+        assert!(d.find_word("c").is_none());
+        assert_eq!(d.find_name(CodeAddress::from_index(4)), None);
+        assert_eq!(d.last_word_name(), None);
+    }
+
+    #[test]
+    fn test_checkpoint_rollback_restores_redefined_word() {
+        let mut d = Dictionary::new();
+        d.define(String::from("a"), Word::new(CodeAddress::from_index(0)));
+        let cp = d.checkpoint();
+        // redefine an existing name past the checkpoint
+        d.define(String::from("a"), Word::new(CodeAddress::from_index(4)));
+        d.rollback_to(&cp);
+        // the name is back on its captured code, not the truncated one
+        let word = d.find_word("a").unwrap();
+        assert_eq!(word.code(), CodeAddress::from_index(0));
+        assert_eq!(
+            d.find_name(CodeAddress::from_index(0)).map(String::as_str),
+            Some("a")
+        );
+        assert_eq!(d.find_name(CodeAddress::from_index(4)), None);
+    }
+
+    #[test]
+    fn test_local_dictionary() {
+        let mut d = LocalDictionary::new();
+        assert_eq!(d.define(String::from("x")), 0);
+        assert_eq!(d.define(String::from("y")), 1);
+        assert_eq!(d.find("y"), Some(1));
+        assert_eq!(d.find("z"), None);
+        assert_eq!(d.get_all_local_names(), &[String::from("x"), String::from("y")]);
+        d.clear();
+        assert_eq!(d.find("x"), None);
+    }
+}
@@ -0,0 +1,7 @@
+//! exst core library
+//!
+//! This crate contains the language core of exst: the tokenizer,
+//! the resource loader and the virtual machine with its primitive words.
+//! Front ends such as the CLI build on top of this crate.
+
+pub mod lang;
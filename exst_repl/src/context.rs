@@ -0,0 +1,199 @@
+//! command line context
+
+use std::path::PathBuf;
+
+/// parsed command line of the interpreter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    script_name: Option<String>,
+    args: Vec<String>,
+    debug_mode: bool,
+    quiet: bool,
+    dump_on_error: bool,
+    require_empty_stack: bool,
+    project_root: PathBuf,
+    history_file: Option<PathBuf>,
+}
+impl Context {
+    /// parse a command line
+    ///
+    /// # Arguments
+    /// * `args` - the raw arguments, including the program name
+    pub fn new<I: Iterator<Item = String>>(mut args: I) -> Result<Context, String> {
+        let _program = args.next();
+        let mut context = Context {
+            script_name: None,
+            args: Vec::new(),
+            debug_mode: false,
+            quiet: false,
+            dump_on_error: false,
+            require_empty_stack: false,
+            project_root: PathBuf::from("."),
+            history_file: None,
+        };
+        while let Some(arg) = args.next() {
+            context.parse_arg(arg, &mut args)?;
+        }
+        Ok(context)
+    }
+
+    /// handle one argument, consuming its value from the rest if needed
+    fn parse_arg<I: Iterator<Item = String>>(
+        &mut self,
+        arg: String,
+        rest: &mut I,
+    ) -> Result<(), String> {
+        match arg.as_str() {
+            "-a" | "--arg" => {
+                let value = rest
+                    .next()
+                    .ok_or_else(|| String::from("-a requires a value"))?;
+                self.args.push(value);
+            }
+            "-r" | "--root" => {
+                let value = rest
+                    .next()
+                    .ok_or_else(|| String::from("-r requires a value"))?;
+                self.project_root = PathBuf::from(value);
+            }
+            "-d" | "--debug" => {
+                self.debug_mode = true;
+            }
+            "-q" | "--quiet" => {
+                self.quiet = true;
+            }
+            "--dump-on-error" => {
+                self.dump_on_error = true;
+            }
+            "--require-empty-stack" => {
+                self.require_empty_stack = true;
+            }
+            "-H" | "--history" => {
+                let value = rest
+                    .next()
+                    .ok_or_else(|| String::from("-H requires a value"))?;
+                self.history_file = Some(PathBuf::from(value));
+            }
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option: {}", arg));
+            }
+            _ => {
+                if self.script_name.is_none() {
+                    self.script_name = Some(arg);
+                } else {
+                    return Err(format!("unexpected argument: {}", arg));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// resource name of the script to run
+    pub fn script_name(&self) -> Option<&String> {
+        self.script_name.as_ref()
+    }
+    /// arguments passed to the script
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+    /// true if the interpreter drops into a REPL on errors
+    pub fn debug_mode(&self) -> bool {
+        self.debug_mode
+    }
+    /// true if the banner and the interactive prompt are suppressed
+    pub fn quiet(&self) -> bool {
+        self.quiet
+    }
+    /// true if the full machine state is dumped on an uncaught error
+    pub fn dump_on_error(&self) -> bool {
+        self.dump_on_error
+    }
+    /// true if values left on the data stack fail the run
+    pub fn require_empty_stack(&self) -> bool {
+        self.require_empty_stack
+    }
+    /// base directory of `:path` resources
+    pub fn project_root(&self) -> &PathBuf {
+        &self.project_root
+    }
+    /// path of the interactive history file, if any
+    pub fn history_file(&self) -> Option<&PathBuf> {
+        self.history_file.as_ref()
+    }
+
+    /// usage text of the interpreter
+    pub fn usage() -> String {
+        String::from(
+            "usage: exst [options] [script]\n\
+             \n\
+             options:\n\
+             \x20 -a, --arg <value>   pass an argument to the script (repeatable)\n\
+             \x20 -r, --root <path>   base directory of :path resources\n\
+             \x20 -d, --debug         drop into a REPL on errors\n\
+             \x20 -q, --quiet         suppress the banner and the prompt\n\
+             \x20 -H, --history <path> append interactive input to a history file\n\
+             \x20 --dump-on-error     dump the full machine state on an uncaught error\n\
+             \x20 --require-empty-stack fail when values are left on the data stack\n",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Context, String> {
+        let full: Vec<String> = std::iter::once(String::from("exst"))
+            .chain(args.iter().map(|s| String::from(*s)))
+            .collect();
+        Context::new(full.into_iter())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let c = parse(&[]).unwrap();
+        assert_eq!(c.script_name(), None);
+        assert!(c.args().is_empty());
+        assert!(!c.debug_mode());
+        assert!(!c.require_empty_stack());
+        assert_eq!(c.project_root(), &PathBuf::from("."));
+    }
+
+    #[test]
+    fn test_full_command_line() {
+        let c = parse(&[
+            "-a",
+            "x",
+            "-a",
+            "y",
+            "-d",
+            "-q",
+            "-r",
+            "/tmp",
+            "--require-empty-stack",
+            "main",
+        ])
+        .unwrap();
+        assert_eq!(c.script_name(), Some(&String::from("main")));
+        assert_eq!(c.args(), &[String::from("x"), String::from("y")]);
+        assert!(c.debug_mode());
+        assert!(c.quiet());
+        assert!(c.require_empty_stack());
+        assert_eq!(c.project_root(), &PathBuf::from("/tmp"));
+    }
+
+    #[test]
+    fn test_arg_with_spaces_stays_one_element() {
+        // each -a value arrives as one element from the shell and must
+        // not be re-split on whitespace
+        let c = parse(&["-a", "hello world"]).unwrap();
+        assert_eq!(c.args(), &[String::from("hello world")]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&["-a"]).is_err());
+        assert!(parse(&["-x"]).is_err());
+        assert!(parse(&["one", "two"]).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! script execution
+
+use crate::context::Context;
+use exst_core::lang::primitive;
+use exst_core::lang::resource::Resources;
+use exst_core::lang::resource::StdResources;
+use exst_core::lang::resource::STDIN_RESOURCE_NAME;
+use exst_core::lang::vm::dump::dump_all_info;
+use exst_core::lang::vm::dump::dump_vm_state;
+use exst_core::lang::vm::Vm;
+use std::io::IsTerminal;
+use std::rc::Rc;
+
+/// exit code of a successful run
+const EXIT_SUCCESS: i32 = 0;
+/// exit code of a failed run
+const EXIT_FAILURE: i32 = 1;
+/// default interactive prompt
+const DEFAULT_PROMPT: &str = "exst> ";
+
+/// interactive input history
+///
+/// Lines are kept in order of entry; a line identical to the previous
+/// one is collapsed. The history is persisted as one line per entry.
+pub struct History {
+    path: std::path::PathBuf,
+    lines: Vec<String>,
+}
+impl History {
+    /// load the history from the given file, if it exists
+    pub fn load(path: std::path::PathBuf) -> Self {
+        let lines = std::fs::read_to_string(&path)
+            .map(|body| body.lines().map(String::from).collect())
+            .unwrap_or_default();
+        History { path, lines }
+    }
+    /// append a line, collapsing a repeat of the previous one
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim_end();
+        if line.is_empty() || self.lines.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.lines.push(String::from(line));
+    }
+    /// all lines, oldest first
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+    /// write the history back to its file
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut body = self.lines.join("\n");
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        std::fs::write(&self.path, body)
+    }
+}
+
+/// runs a script described by a `Context`
+pub struct Executor {
+    context: Context,
+    prompt: String,
+}
+impl Executor {
+    /// create a new instance
+    ///
+    /// # Arguments
+    /// * `context` - the parsed command line
+    pub fn new(context: Context) -> Self {
+        Executor {
+            context,
+            prompt: String::from(DEFAULT_PROMPT),
+        }
+    }
+
+    /// replace the interactive prompt
+    pub fn set_prompt(&mut self, prompt: String) {
+        self.prompt = prompt;
+    }
+
+    /// run the script with the standard resources
+    pub fn exec(&self) -> i32 {
+        let resources = StdResources::new(self.context.project_root().clone());
+        self.exec_internal(resources, std::io::stdin().is_terminal())
+    }
+
+    /// run the script with the given resources
+    ///
+    /// The input is treated as interactive, so a `%STDIN` run prompts
+    /// unless the context is quiet.
+    ///
+    /// # Arguments
+    /// * `resources` - resource implementation used by the machine
+    pub fn exec_with_resources<R: Resources + 'static>(&self, resources: R) -> i32 {
+        self.exec_internal(resources, true)
+    }
+
+    /// run the script
+    ///
+    /// # Arguments
+    /// * `resources` - resource implementation used by the machine
+    /// * `interactive` - true when the standard input is a terminal
+    fn exec_internal<R: Resources + 'static>(&self, resources: R, interactive: bool) -> i32 {
+        let resources = Rc::new(resources);
+        let mut vm: Vm<i32, i32> = Vm::new(Rc::clone(&resources));
+        if let Err(e) = primitive::initialize(&mut vm) {
+            vm.resources()
+                .write_stderr(&format!("initialization error: {:?}\n", e))
+                .ok();
+            return EXIT_FAILURE;
+        }
+        let script_name = match self.context.script_name() {
+            Some(name) => name.clone(),
+            None => String::from(STDIN_RESOURCE_NAME),
+        };
+        let decorate = script_name == STDIN_RESOURCE_NAME
+            && interactive
+            && !self.context.quiet();
+        if decorate {
+            resources
+                .write_stderr(&format!("exst {}\n", env!("CARGO_PKG_VERSION")))
+                .ok();
+        }
+        let mut stream = match vm.resources().get_token_iterator(&script_name) {
+            Ok(stream) => stream,
+            Err(e) => {
+                vm.resources()
+                    .write_stderr(&format!("error: resource error: {:?}\n", e))
+                    .ok();
+                return EXIT_FAILURE;
+            }
+        };
+        if decorate {
+            // the hook fires before the read blocks on the terminal,
+            // so the prompt shows up before the line is entered
+            let resources = Rc::clone(&resources);
+            let prompt = self.prompt.clone();
+            stream.set_line_start_hook(Box::new(move || {
+                resources.write_stderr(&prompt).ok();
+            }));
+        }
+        let history = if decorate {
+            self.context
+                .history_file()
+                .map(|path| Rc::new(std::cell::RefCell::new(History::load(path.clone()))))
+        } else {
+            None
+        };
+        if let Some(history) = history.as_ref() {
+            // record lines as they are entered, not ahead of time
+            let history = Rc::clone(history);
+            stream.set_line_consumed_hook(Box::new(move |line| {
+                history.borrow_mut().add(line);
+            }));
+        }
+        vm.call_script(stream);
+        let code = self.run_to_completion(&mut vm);
+        if let Some(history) = history {
+            // history is best effort; a broken file never stops a run
+            history.borrow().save().ok();
+        }
+        code
+    }
+
+    /// drive the machine to the end of its input
+    ///
+    /// # Arguments
+    /// * `vm` - the machine, with its script already set up
+    fn run_to_completion(&self, vm: &mut Vm<i32, i32>) -> i32 {
+        let mut result = vm.exec_with_args(self.context.args());
+        while let Err(e) = result {
+            let position = vm.current_position();
+            let message = format!(
+                "error: {} ({}:{}:{})\n",
+                e, position.script_name, position.line_number, position.column_number
+            );
+            vm.resources().write_stderr(&message).ok();
+            if !self.context.debug_mode() {
+                if self.context.dump_on_error() {
+                    let mut lines = String::new();
+                    dump_all_info(vm, &mut |line| {
+                        lines.push_str(line);
+                        lines.push('\n');
+                    });
+                    vm.resources().write_stderr(&lines).ok();
+                }
+                return EXIT_FAILURE;
+            }
+            // debug mode recovers: dump the state, clear the stacks
+            // and keep interpreting the remaining input
+            let mut lines = String::new();
+            dump_vm_state(vm, &mut |line| {
+                lines.push_str(line);
+                lines.push('\n');
+            });
+            vm.resources().write_stderr(&lines).ok();
+            vm.reset_execution();
+            result = vm.exec();
+        }
+        if self.context.require_empty_stack() && vm.data_stack().here() != 0 {
+            vm.resources()
+                .write_stderr(&format!(
+                    "warning: {} value(s) left on the data stack\n",
+                    vm.data_stack().here()
+                ))
+                .ok();
+            return EXIT_FAILURE;
+        }
+        EXIT_SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exst_core::lang::resource::BufferResources;
+
+    fn context(args: &[&str]) -> Context {
+        let full: Vec<String> = std::iter::once(String::from("exst"))
+            .chain(args.iter().map(|s| String::from(*s)))
+            .collect();
+        Context::new(full.into_iter()).unwrap()
+    }
+
+    fn resources_with_script(name: &str, body: &str) -> Rc<BufferResources> {
+        let mut r = BufferResources::new();
+        r.add_resource(String::from(name), String::from(body));
+        Rc::new(r)
+    }
+
+    #[test]
+    fn test_exec_script() {
+        let resources = resources_with_script("main", "1 2 + .");
+        let executor = Executor::new(context(&["main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 0);
+        assert_eq!(resources.stdout(), "3 ");
+    }
+
+    #[test]
+    fn test_exec_with_args() {
+        let resources = resources_with_script("main", "0 arg .");
+        let executor = Executor::new(context(&["-a", "hello", "main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 0);
+        assert_eq!(resources.stdout(), "hello ");
+    }
+
+    #[test]
+    fn test_require_empty_stack() {
+        // a leftover value is fine by default
+        let resources = resources_with_script("main", "1 2 +");
+        let executor = Executor::new(context(&["main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 0);
+        // but fails the run when the policy is on
+        let resources = resources_with_script("main", "1 2 +");
+        let executor = Executor::new(context(&["--require-empty-stack", "main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 1);
+        assert!(resources.stderr().contains("left on the data stack"));
+    }
+
+    #[test]
+    fn test_exec_error() {
+        let resources = resources_with_script("main", "no-such-word");
+        let executor = Executor::new(context(&["main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 1);
+        assert!(resources.stderr().contains("undefined word: no-such-word"));
+        assert!(resources.stderr().contains("main:1:"));
+    }
+
+    #[test]
+    fn test_exec_error_prints_position() {
+        let resources = resources_with_script("main", "1 2 +\n\" unterminated");
+        let executor = Executor::new(context(&["main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 1);
+        let stderr = resources.stderr();
+        assert!(stderr.contains("tokenize error in main at line 2"));
+    }
+
+    #[test]
+    fn test_exec_missing_script() {
+        let resources = Rc::new(BufferResources::new());
+        let executor = Executor::new(context(&["main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 1);
+        assert!(resources.stderr().contains("ResourceNotFound"));
+    }
+
+    #[test]
+    fn test_dump_on_error() {
+        let resources = resources_with_script("main", "1 no-such-word");
+        let executor = Executor::new(context(&["--dump-on-error", "main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 1);
+        let stderr = resources.stderr();
+        assert!(stderr.contains("[data stack]"));
+        assert!(stderr.contains("data stack depth: 1"));
+    }
+
+    #[test]
+    fn test_exec_debug_mode_dumps_state() {
+        let resources = resources_with_script("main", "1 no-such-word");
+        let executor = Executor::new(context(&["-d", "main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 0);
+        assert!(resources.stderr().contains("undefined word: no-such-word"));
+        assert!(resources.stderr().contains("data stack depth"));
+    }
+
+    #[test]
+    fn test_exec_debug_mode_recovers_after_error() {
+        let resources = resources_with_script("main", "no-such-word\n1 2 + .");
+        let executor = Executor::new(context(&["-d", "main"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 0);
+        assert!(resources.stderr().contains("undefined word: no-such-word"));
+        assert_eq!(resources.stdout(), "3 ");
+    }
+
+    #[test]
+    fn test_history_collapses_repeats() {
+        let path = std::env::temp_dir().join("exst_history_test");
+        std::fs::remove_file(&path).ok();
+        let mut history = History::load(path.clone());
+        history.add("1 2 +");
+        history.add("1 2 +");
+        history.add(".s");
+        history.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1 2 +\n.s\n");
+        // a fresh load continues where the file left off
+        let mut history = History::load(path.clone());
+        history.add(".s");
+        history.add("words");
+        assert_eq!(history.lines(), &[String::from("1 2 +"), String::from(".s"), String::from("words")]);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_history_written_by_executor() {
+        let path = std::env::temp_dir().join("exst_history_exec_test");
+        std::fs::remove_file(&path).ok();
+        let resources = resources_with_script(STDIN_RESOURCE_NAME, "1 2 +\n1 2 +\n.s");
+        let executor = Executor::new(context(&["-H", path.to_str().unwrap()]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "1 2 +\n.s\n");
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_banner_and_prompt_per_line() {
+        let resources = resources_with_script(STDIN_RESOURCE_NAME, "1 2 +\n3 4 +");
+        let executor = Executor::new(context(&[]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 0);
+        let stderr = resources.stderr();
+        assert!(stderr.contains(&format!("exst {}", env!("CARGO_PKG_VERSION"))));
+        assert_eq!(stderr.matches("exst> ").count(), 2);
+    }
+
+    #[test]
+    fn test_quiet_suppresses_banner_and_prompt() {
+        let resources = resources_with_script(STDIN_RESOURCE_NAME, "1 2 +\n3 4 +");
+        let executor = Executor::new(context(&["-q"]));
+        assert_eq!(executor.exec_with_resources(Rc::clone(&resources)), 0);
+        assert_eq!(resources.stderr(), "");
+    }
+}
@@ -0,0 +1,7 @@
+//! exst front end library
+//!
+//! Parses the command line into a `Context` and drives a VM with an
+//! `Executor`. The CLI binary is a thin wrapper around this crate.
+
+pub mod context;
+pub mod exec;